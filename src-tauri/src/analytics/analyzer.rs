@@ -0,0 +1,167 @@
+use super::color::ColorAnalysis;
+use super::scene::{self, SceneDetector};
+use super::{AnalyticsConfig, SampledFrame};
+use crate::capture::Frame;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// Everything an analyzer can look at for one frame. The sampled grid, histogram and
+/// colour analysis are computed once by the pipeline and shared by all analyzers.
+pub struct FrameContext<'a> {
+    pub time: f64,
+    pub frame: &'a Frame,
+    pub sample: &'a SampledFrame,
+    pub histogram: &'a [u32; 256],
+    pub colors: &'a ColorAnalysis,
+    /// Audio level (0-1) captured alongside the frame, `None` without audio
+    pub audio_level: Option<f64>,
+}
+
+/// A discrete occurrence reported by an analyzer, e.g. a scene change
+#[derive(Clone, Debug, Serialize)]
+pub struct AnalyzerEvent {
+    pub kind: String,
+    pub analyzer: String,
+    pub data: Value,
+}
+
+#[derive(Default)]
+pub struct AnalyzerOutput {
+    /// Named values flattened into the timeline entry, usually numbers
+    pub values: Vec<(String, Value)>,
+    pub events: Vec<AnalyzerEvent>,
+    /// Messages for the user, raised as analytics warnings at the frame's time
+    pub warnings: Vec<String>,
+}
+
+impl AnalyzerOutput {
+    pub fn series(&mut self, name: impl Into<String>, value: f64) {
+        self.value(name, value);
+    }
+
+    pub fn value(&mut self, name: impl Into<String>, value: impl Serialize) {
+        self.values.push((name.into(), json!(value)));
+    }
+
+    /// A value set by an analyzer that ran earlier on this frame
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.values
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value)
+    }
+
+    pub fn number(&self, name: &str) -> Option<f64> {
+        self.get(name).and_then(Value::as_f64)
+    }
+
+    pub fn warn(&mut self, message: String) {
+        self.warnings.push(message);
+    }
+
+    pub fn event(&mut self, analyzer: &str, kind: impl Into<String>, data: Value) {
+        self.events.push(AnalyzerEvent {
+            kind: kind.into(),
+            analyzer: analyzer.to_string(),
+            data,
+        });
+    }
+}
+
+/// A per-frame analytic. Each analyzed frame may add named values and discrete events to
+/// the timeline; `finish` returns a summary for the meta.json sidecar. Analyzers run in
+/// order, built-ins first, and can read what earlier ones produced from the output.
+pub trait FrameAnalyzer: Send {
+    fn name(&self) -> &str;
+
+    /// Called once when the analyzer is registered with a pipeline
+    fn configure(&mut self, _config: &AnalyticsConfig) {}
+
+    fn process_frame(&mut self, context: &FrameContext, output: &mut AnalyzerOutput);
+
+    /// Called when the recording is finalized, `end` is the last analyzed timestamp
+    fn finish(&mut self, _end: f64) -> Value {
+        Value::Null
+    }
+}
+
+/// Mean luma of the frame (0-1)
+pub struct BrightnessAnalyzer;
+
+impl FrameAnalyzer for BrightnessAnalyzer {
+    fn name(&self) -> &str {
+        "brightness"
+    }
+
+    fn process_frame(&mut self, context: &FrameContext, output: &mut AnalyzerOutput) {
+        output.series(
+            "brightness",
+            super::average_brightness(context.sample, context.histogram),
+        );
+    }
+}
+
+/// Share of the frame covered by its most common colour, plus hue and saturation
+pub struct DominanceAnalyzer;
+
+impl FrameAnalyzer for DominanceAnalyzer {
+    fn name(&self) -> &str {
+        "dominance"
+    }
+
+    fn process_frame(&mut self, context: &FrameContext, output: &mut AnalyzerOutput) {
+        let colors = context.colors;
+        let dominance = colors.palette.first().map(|c| c.weight).unwrap_or(0.0);
+        output.series("colorDominance", dominance);
+        output.series("saturation", colors.saturation);
+        if let Some(hue) = colors.dominant_hue {
+            output.series("dominantHue", hue);
+        }
+    }
+}
+
+/// Wraps the configured [`SceneDetector`], emitting a `scene_change` event per cut
+#[derive(Default)]
+pub struct SceneChangeAnalyzer {
+    detector: Option<Box<dyn SceneDetector>>,
+    changes: usize,
+}
+
+impl FrameAnalyzer for SceneChangeAnalyzer {
+    fn name(&self) -> &str {
+        "scene"
+    }
+
+    fn configure(&mut self, config: &AnalyticsConfig) {
+        self.detector = Some(scene::create_detector(&config.scene));
+    }
+
+    fn process_frame(&mut self, context: &FrameContext, output: &mut AnalyzerOutput) {
+        let Some(detector) = self.detector.as_mut() else {
+            return;
+        };
+
+        let decision = detector.detect(context.sample, context.histogram);
+        output.series("sceneConfidence", decision.confidence);
+        if decision.changed {
+            self.changes += 1;
+            tracing::debug!("Scene change at {:.1}s", context.time);
+            output.event(
+                "scene",
+                "scene_change",
+                json!({
+                    "confidence": decision.confidence,
+                    // Wall clock capture time, for matching against external logs
+                    "captured_at": context.frame.timestamp,
+                }),
+            );
+        }
+    }
+
+    fn finish(&mut self, _end: f64) -> Value {
+        json!({
+            "detector": self.detector.as_ref().map(|d| d.name()),
+            "changes": self.changes,
+        })
+    }
+}
@@ -0,0 +1,185 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChapterConfig {
+    /// Shortest chapter in seconds, scene changes closer to the previous boundary are ignored
+    pub min_duration: f64,
+    /// Scene changes less than this many seconds apart are one transition, e.g. a window
+    /// switch animating over several frames
+    pub cluster_gap: f64,
+}
+
+impl Default for ChapterConfig {
+    fn default() -> Self {
+        Self {
+            min_duration: 30.0,
+            cluster_gap: 3.0,
+        }
+    }
+}
+
+/// A titled section of the recording, in session seconds
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Chapter {
+    pub start: f64,
+    pub end: f64,
+    pub title: String,
+    /// Derived from scene changes rather than added by the user
+    #[serde(default)]
+    pub auto: bool,
+}
+
+/// Turns the stream of scene changes into chapter boundaries
+pub struct ChapterDetector {
+    config: ChapterConfig,
+    /// First and last change of the cluster still being extended
+    cluster: Option<(f64, f64)>,
+    boundaries: Vec<f64>,
+}
+
+impl ChapterDetector {
+    pub fn new(config: ChapterConfig) -> Self {
+        Self {
+            config,
+            cluster: None,
+            boundaries: Vec::new(),
+        }
+    }
+
+    /// Record a scene change at `time`
+    pub fn scene_change(&mut self, time: f64) {
+        match self.cluster {
+            Some((first, last)) if time - last <= self.config.cluster_gap => {
+                self.cluster = Some((first, time));
+            }
+            Some((_, last)) => {
+                self.close_cluster(last);
+                self.cluster = Some((time, time));
+            }
+            None => self.cluster = Some((time, time)),
+        }
+    }
+
+    /// A chapter starts once the transition has settled, i.e. at the cluster's last change
+    fn close_cluster(&mut self, boundary: f64) {
+        let previous = self.boundaries.last().copied().unwrap_or(0.0);
+        if boundary - previous >= self.config.min_duration {
+            tracing::debug!("Chapter boundary at {:.1}s", boundary);
+            self.boundaries.push(boundary);
+        }
+    }
+
+    /// Chapters covering `[0, end]`, including a cluster still open at `end`
+    pub fn chapters(&self, end: f64) -> Vec<Chapter> {
+        let mut boundaries = self.boundaries.clone();
+        if let Some((_, last)) = self.cluster {
+            let previous = boundaries.last().copied().unwrap_or(0.0);
+            if last - previous >= self.config.min_duration {
+                boundaries.push(last);
+            }
+        }
+        // The final chapter has to be long enough too, otherwise it joins the one before
+        while boundaries
+            .last()
+            .is_some_and(|b| end - b < self.config.min_duration)
+        {
+            boundaries.pop();
+        }
+
+        let starts = std::iter::once(0.0).chain(boundaries.iter().copied());
+        let ends = boundaries.iter().copied().chain(std::iter::once(end));
+        starts
+            .zip(ends)
+            .enumerate()
+            .map(|(i, (start, end))| Chapter {
+                start,
+                end,
+                title: format!("Chapter {}", i + 1),
+                auto: true,
+            })
+            .collect()
+    }
+}
+
+/// Start a user chapter titled `title` at `start`. The chapter containing it is split,
+/// or retitled when it already starts there. Returns false if `start` is out of range.
+pub fn split(chapters: &mut Vec<Chapter>, start: f64, title: String) -> bool {
+    let Some(index) = chapters
+        .iter()
+        .position(|c| start >= c.start && start < c.end)
+    else {
+        return false;
+    };
+
+    let chapter = &mut chapters[index];
+    if start - chapter.start < 0.001 {
+        chapter.title = title;
+        chapter.auto = false;
+        return true;
+    }
+
+    let end = chapter.end;
+    chapter.end = start;
+    chapters.insert(
+        index + 1,
+        Chapter {
+            start,
+            end,
+            title,
+            auto: false,
+        },
+    );
+    true
+}
+
+/// WebVTT chapter track, one cue per chapter
+pub fn to_webvtt(chapters: &[Chapter]) -> String {
+    let mut vtt = String::from("WEBVTT\n");
+    for (i, chapter) in chapters.iter().enumerate() {
+        vtt.push_str(&format!(
+            "\n{}\n{} --> {}\n{}\n",
+            i + 1,
+            vtt_timestamp(chapter.start),
+            vtt_timestamp(chapter.end),
+            chapter.title.replace("-->", "->").replace('\n', " ")
+        ));
+    }
+    vtt
+}
+
+/// ffmpeg metadata file, muxed into Matroska as the container's chapters
+pub fn to_ffmetadata(chapters: &[Chapter]) -> String {
+    let mut metadata = String::from(";FFMETADATA1\n");
+    for chapter in chapters {
+        metadata.push_str(&format!(
+            "\n[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            (chapter.start * 1000.0).round() as u64,
+            (chapter.end * 1000.0).round() as u64,
+            escape_ffmetadata(&chapter.title)
+        ));
+    }
+    metadata
+}
+
+fn vtt_timestamp(seconds: f64) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+fn escape_ffmetadata(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
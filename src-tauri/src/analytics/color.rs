@@ -0,0 +1,174 @@
+use super::SampledFrame;
+use serde::Serialize;
+
+/// Pixels with less saturation than this don't vote for the dominant hue
+const MIN_HUE_SATURATION: f64 = 0.15;
+const HUE_BINS: usize = 36;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PaletteColor {
+    pub hex: String,
+    pub rgb: [u8; 3],
+    /// Share of sampled pixels (0-1) closer to this colour than to any other in the palette
+    pub weight: f64,
+}
+
+pub struct ColorAnalysis {
+    /// Heaviest colour first
+    pub palette: Vec<PaletteColor>,
+    /// Degrees (0-360), `None` when the frame is essentially greyscale
+    pub dominant_hue: Option<f64>,
+    /// Mean HSV saturation (0-1)
+    pub saturation: f64,
+}
+
+pub fn analyze(sample: &SampledFrame, palette_size: usize) -> ColorAnalysis {
+    // Palette extraction doesn't need every sampled pixel
+    let pixels: Vec<[u8; 3]> = sample.rgb.iter().step_by(4).copied().collect();
+
+    let mut hue_votes = [0.0f64; HUE_BINS];
+    let mut saturation_sum = 0.0;
+    for &pixel in &pixels {
+        let (hue, saturation) = hue_saturation(pixel);
+        saturation_sum += saturation;
+        if saturation >= MIN_HUE_SATURATION {
+            hue_votes[(hue / 360.0 * HUE_BINS as f64) as usize % HUE_BINS] += saturation;
+        }
+    }
+
+    let dominant_hue = hue_votes
+        .iter()
+        .enumerate()
+        .filter(|(_, &votes)| votes > 0.0)
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(bin, _)| (bin as f64 + 0.5) * 360.0 / HUE_BINS as f64);
+
+    let saturation = if pixels.is_empty() {
+        0.0
+    } else {
+        saturation_sum / pixels.len() as f64
+    };
+
+    ColorAnalysis {
+        palette: median_cut(pixels, palette_size),
+        dominant_hue,
+        saturation,
+    }
+}
+
+/// Split the colour space into `size` boxes, each time halving the box with the widest
+/// channel range at its median. Median splits leave every box with about the same pixel
+/// count, so the box averages are weighted by how many pixels are nearest to them.
+fn median_cut(pixels: Vec<[u8; 3]>, size: usize) -> Vec<PaletteColor> {
+    let total = pixels.len();
+    if total == 0 || size == 0 {
+        return Vec::new();
+    }
+
+    let mut boxes = vec![pixels.clone()];
+    while boxes.len() < size {
+        let Some((index, channel)) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .map(|(i, b)| {
+                let (channel, range) = widest_channel(b);
+                (i, channel, range)
+            })
+            .filter(|(_, _, range)| *range > 0)
+            .max_by_key(|(_, _, range)| *range)
+            .map(|(i, channel, _)| (i, channel))
+        else {
+            break;
+        };
+
+        let mut bucket = boxes.swap_remove(index);
+        bucket.sort_unstable_by_key(|p| p[channel]);
+        let upper = bucket.split_off(bucket.len() / 2);
+        boxes.push(bucket);
+        boxes.push(upper);
+    }
+
+    let colors: Vec<[u8; 3]> = boxes
+        .iter()
+        .map(|bucket| {
+            let mut sum = [0u64; 3];
+            for pixel in bucket {
+                for (total, &value) in sum.iter_mut().zip(pixel) {
+                    *total += value as u64;
+                }
+            }
+            let n = bucket.len() as u64;
+            [
+                (sum[0] / n) as u8,
+                (sum[1] / n) as u8,
+                (sum[2] / n) as u8,
+            ]
+        })
+        .collect();
+
+    let mut counts = vec![0usize; colors.len()];
+    for pixel in &pixels {
+        if let Some(nearest) = (0..colors.len()).min_by_key(|&i| distance(*pixel, colors[i])) {
+            counts[nearest] += 1;
+        }
+    }
+
+    let mut palette: Vec<PaletteColor> = colors
+        .into_iter()
+        .zip(counts)
+        .map(|(rgb, count)| PaletteColor {
+            hex: format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2]),
+            rgb,
+            weight: count as f64 / total as f64,
+        })
+        .collect();
+
+    palette.sort_by(|a, b| b.weight.total_cmp(&a.weight));
+    palette
+}
+
+/// Squared RGB distance
+fn distance(a: [u8; 3], b: [u8; 3]) -> u32 {
+    a.iter()
+        .zip(&b)
+        .map(|(&x, &y)| (x as i32 - y as i32).pow(2) as u32)
+        .sum()
+}
+
+fn widest_channel(pixels: &[[u8; 3]]) -> (usize, u8) {
+    let mut min = [u8::MAX; 3];
+    let mut max = [u8::MIN; 3];
+    for pixel in pixels {
+        for (c, &value) in pixel.iter().enumerate() {
+            min[c] = min[c].min(value);
+            max[c] = max[c].max(value);
+        }
+    }
+    (0..3)
+        .map(|c| (c, max[c] - min[c]))
+        .max_by_key(|(_, range)| *range)
+        .unwrap_or((0, 0))
+}
+
+/// HSV hue in degrees and saturation (0-1)
+fn hue_saturation([r, g, b]: [u8; 3]) -> (f64, f64) {
+    let (r, g, b) = (r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+
+    let saturation = if max > 0.0 { delta / max } else { 0.0 };
+    if delta == 0.0 {
+        return (0.0, saturation);
+    }
+
+    let hue = if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    (hue, saturation)
+}
@@ -0,0 +1,86 @@
+use super::analyzer::{AnalyzerOutput, FrameAnalyzer, FrameContext};
+use serde::{Deserialize, Serialize};
+
+/// What the session does with frames while the screen is idle
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum IdleAction {
+    /// Keep encoding every frame
+    Ignore,
+    /// Stop feeding identical frames to the encoder and hold the last one instead, so the
+    /// video stays on the session clock
+    SkipFrames,
+    /// Behave like a manual pause until the screen changes again
+    Pause,
+}
+
+/// A stretch of the recording (in session seconds) where the screen did not change.
+/// With [`IdleAction::Pause`] only the part kept in the video is covered.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct IdleSpan {
+    pub start: f64,
+    pub end: f64,
+}
+
+pub struct IdleDetector {
+    threshold: f64,
+    min_duration: f64,
+    still_since: Option<f64>,
+    idle: bool,
+    spans: Vec<IdleSpan>,
+}
+
+impl IdleDetector {
+    pub fn new(threshold: f64, min_duration: f64) -> Self {
+        Self {
+            threshold,
+            min_duration,
+            still_since: None,
+            idle: false,
+            spans: Vec::new(),
+        }
+    }
+
+    /// Feed the frame's motion energy, returns whether the screen is currently idle
+    pub fn update(&mut self, time: f64, motion: f64) -> bool {
+        if motion < self.threshold {
+            let since = *self.still_since.get_or_insert(time);
+            if !self.idle && time - since >= self.min_duration {
+                self.idle = true;
+                tracing::info!("Screen idle since {:.1}s", since);
+            }
+        } else if let Some(start) = self.still_since.take() {
+            if self.idle {
+                self.idle = false;
+                self.spans.push(IdleSpan { start, end: time });
+                tracing::info!("Screen active again at {:.1}s", time);
+            }
+        }
+        self.idle
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.idle
+    }
+
+    /// Completed spans, plus the one still open at `end`
+    pub fn spans(&self, end: f64) -> Vec<IdleSpan> {
+        let mut spans = self.spans.clone();
+        if let (true, Some(start)) = (self.idle, self.still_since) {
+            spans.push(IdleSpan { start, end });
+        }
+        spans
+    }
+}
+
+/// Runs after the motion analyzer and reads its `motion` value
+impl FrameAnalyzer for IdleDetector {
+    fn name(&self) -> &str {
+        "idle"
+    }
+
+    fn process_frame(&mut self, context: &FrameContext, output: &mut AnalyzerOutput) {
+        let motion = output.number("motion").unwrap_or(0.0);
+        output.value("idle", self.update(context.time, motion));
+    }
+}
@@ -0,0 +1,747 @@
+pub mod analyzer;
+pub mod chapters;
+pub mod color;
+pub mod idle;
+pub mod motion;
+pub mod quality;
+pub mod query;
+pub mod scene;
+pub mod sharpness;
+pub mod store;
+pub mod thumbnails;
+
+use crate::capture::Frame;
+use crate::encryption::RecordingKey;
+use anyhow::Result;
+use analyzer::{
+    AnalyzerEvent, AnalyzerOutput, BrightnessAnalyzer, DominanceAnalyzer, FrameAnalyzer,
+    FrameContext, SceneChangeAnalyzer,
+};
+use chapters::{Chapter, ChapterConfig, ChapterDetector};
+use color::PaletteColor;
+use idle::{IdleAction, IdleDetector};
+use motion::MotionHeatmap;
+use quality::{QualityChecker, QualityConfig};
+use query::{RecentEntries, StreamingBuckets, TimelineQuery};
+use rayon::prelude::*;
+use scene::SceneDetectionConfig;
+use sharpness::SharpnessAnalyzer;
+use store::{DownsampledTimeline, StoreReader, TimelineStore};
+use thumbnails::{ThumbnailConfig, ThumbnailStrip};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Tunable analytics settings, supplied by the frontend when a recording starts
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnalyticsConfig {
+    /// Width in pixels of the downsampled grid analytics run on
+    pub analysis_width: u32,
+    /// Only every Nth captured frame is analyzed
+    pub analyze_every_n_frames: u32,
+    /// Audio level (0-1) below which a frame counts as silent
+    pub silence_threshold: f64,
+    /// Minimum length in seconds of a quiet stretch before it is reported as silence
+    pub silence_min_duration: f64,
+    /// Number of colours extracted into each frame's palette
+    pub palette_size: usize,
+    /// Motion heatmap grid size in tiles
+    pub heatmap_columns: usize,
+    pub heatmap_rows: usize,
+    /// Length in seconds of each time window the heatmap keeps separately
+    pub heatmap_window_seconds: f64,
+    /// Mean frame difference (0-1) below which the screen counts as unchanged
+    pub idle_threshold: f64,
+    /// Seconds without change before the screen is considered idle
+    pub idle_min_duration: f64,
+    pub idle_action: IdleAction,
+    pub scene: SceneDetectionConfig,
+    pub quality: QualityConfig,
+    pub chapters: ChapterConfig,
+    pub thumbnails: ThumbnailConfig,
+}
+
+impl Default for AnalyticsConfig {
+    fn default() -> Self {
+        Self {
+            analysis_width: 320,
+            analyze_every_n_frames: 1,
+            silence_threshold: 0.02,
+            silence_min_duration: 2.0,
+            palette_size: 5,
+            heatmap_columns: 32,
+            heatmap_rows: 18,
+            heatmap_window_seconds: 60.0,
+            idle_threshold: 0.002,
+            idle_min_duration: 10.0,
+            idle_action: IdleAction::Ignore,
+            scene: SceneDetectionConfig::default(),
+            quality: QualityConfig::default(),
+            chapters: ChapterConfig::default(),
+            thumbnails: ThumbnailConfig::default(),
+        }
+    }
+}
+
+/// A stretch of the recording (in session seconds) where audio stayed below the threshold
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct SilenceSpan {
+    pub start: f64,
+    pub end: f64,
+}
+
+/// A bookmark dropped by the user during recording, at `time` session seconds
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Marker {
+    pub time: f64,
+    pub label: String,
+    pub kind: String,
+}
+
+/// Points kept in memory for `get_timeline_data`, spread over the whole recording
+const TIMELINE_VIEW_CAPACITY: usize = 1000;
+
+/// What the capture loop needs to know about a frame after analysis
+pub struct FrameSummary {
+    pub idle: bool,
+    /// Warnings raised by this frame, already recorded for the sidecar
+    pub warnings: Vec<AnalyticsWarning>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct AnalyticsWarning {
+    pub time: f64,
+    pub message: String,
+}
+
+pub struct AnalyticsPipeline {
+    timeline_data: Arc<Mutex<TimelineView>>,
+    /// Full resolution timeline, streamed to disk next to the recording
+    timeline_store: TimelineStore,
+    store_failed: bool,
+    events: Vec<Value>,
+    config: AnalyticsConfig,
+    analyze_every_n_frames: u64,
+    frame_index: u64,
+    // Built-in analyzers, run in this order so later ones can read earlier values
+    brightness: BrightnessAnalyzer,
+    dominance: DominanceAnalyzer,
+    scene: SceneChangeAnalyzer,
+    motion: MotionHeatmap,
+    idle: IdleDetector,
+    quality: QualityChecker,
+    sharpness: SharpnessAnalyzer,
+    silence: SilenceDetector,
+    /// Added with [`AnalyticsPipeline::register`], run after the built-ins
+    analyzers: Vec<Box<dyn FrameAnalyzer>>,
+    chapters: ChapterDetector,
+    thumbnails: ThumbnailStrip,
+    markers: Vec<Marker>,
+    /// Markers not yet attached to a timeline entry
+    pending_markers: Vec<Marker>,
+    bitrate_kbps: u32,
+    warnings: Vec<AnalyticsWarning>,
+    last_timestamp: f64,
+    /// What was recorded, see [`crate::capture::CaptureSource::label`]
+    source: Option<String>,
+    /// Video the sidecar describes, when it isn't the path the outputs are named after
+    video_path: Option<PathBuf>,
+    /// Encrypts the timeline store, sidecar, heatmap and thumbnails when set
+    encryption: Option<Arc<RecordingKey>>,
+}
+
+/// Entries a zoomed timeline query reads from disk at most, close to an hour at 30 FPS
+const MAX_STORE_ENTRIES: usize = 100_000;
+
+/// The timeline of a running pipeline, taken with [`AnalyticsPipeline::timeline_reader`]
+pub struct TimelineReader {
+    view: Arc<Mutex<TimelineView>>,
+    store: Option<StoreReader>,
+    /// Session time of the newest entry
+    end: f64,
+}
+
+impl TimelineReader {
+    /// Timeline for the UI. With a cursor (`since`) only newer full resolution entries
+    /// are returned; otherwise the requested range is bucketed down to `max_points`.
+    /// `truncated` is set when entries older than the cursor, or inside the range, are
+    /// missing from the answer.
+    pub async fn get_timeline_data(&self, query: &TimelineQuery) -> Value {
+        let view = self.view.lock().await;
+        if let Some(cursor) = query.since {
+            return view.recent.since(cursor, query);
+        }
+
+        let time = |e: &Value| e["time"].as_f64().unwrap_or(0.0);
+        let max_points = query.max_points.unwrap_or(TIMELINE_VIEW_CAPACITY);
+        let entries: Vec<Value> = view
+            .downsampled
+            .iter()
+            .filter(|e| query.contains(time(e)))
+            .cloned()
+            .collect();
+        let cursor = view.recent.cursor();
+        let zoomed = query.from.is_some() || query.to.is_some();
+        let needs_disk = zoomed && !view.downsampled.is_complete() && entries.len() < max_points;
+        drop(view);
+
+        // Zoomed in past the in-memory resolution, read the range back from disk. Without
+        // it the answer is missing entries the decimated view dropped.
+        if let Some(store) = self.store.as_ref().filter(|_| needs_disk) {
+            match self
+                .read_bucketed(store.clone(), query.clone(), max_points)
+                .await
+            {
+                Ok((entries, complete)) => {
+                    return json!({
+                        "entries": entries,
+                        "cursor": cursor,
+                        "truncated": !complete,
+                    })
+                }
+                Err(e) => tracing::warn!("Failed to read timeline store: {}", e),
+            }
+        }
+
+        json!({
+            "entries": query::bucket(entries, max_points, query.series.as_deref()),
+            "cursor": cursor,
+            "truncated": needs_disk,
+        })
+    }
+
+    /// Bucket the range while it streams from disk on a blocking thread, up to
+    /// `MAX_STORE_ENTRIES` entries. Also returns whether the whole range was read.
+    async fn read_bucketed(
+        &self,
+        store: StoreReader,
+        query: TimelineQuery,
+        max_points: usize,
+    ) -> Result<(Vec<Value>, bool)> {
+        let (start, end) = (query.from.unwrap_or(0.0), query.to.unwrap_or(self.end));
+        tokio::task::spawn_blocking(move || {
+            let mut buckets = StreamingBuckets::new(start, end, max_points, query.series);
+            let complete = store.for_each_in(query.from, query.to, MAX_STORE_ENTRIES, |entry| {
+                buckets.push(entry)
+            })?;
+            Ok((buckets.finish(), complete))
+        })
+        .await?
+    }
+}
+
+/// In-memory side of the timeline: a decimated overview of the whole recording and a
+/// full resolution tail for incremental polling
+struct TimelineView {
+    downsampled: DownsampledTimeline<Value>,
+    recent: RecentEntries,
+}
+
+struct TimelineEntry {
+    timestamp: f64,
+    palette: Vec<PaletteColor>,
+    /// Named values from the analyzers, flattened into the entry's JSON
+    values: Vec<(String, Value)>,
+    events: Vec<AnalyzerEvent>,
+}
+
+impl TimelineEntry {
+    fn to_json(&self) -> Value {
+        let mut value = json!({
+            "time": self.timestamp,
+            "palette": self.palette,
+            "sceneChange": self.events.iter().any(|e| e.kind == "scene_change"),
+            // Stays null until the encoder writes output that can be decoded back
+            "outputSharpness": Value::Null,
+            "events": self.events,
+        });
+        for (name, analyzer_value) in &self.values {
+            value[name.as_str()] = analyzer_value.clone();
+        }
+        value
+    }
+}
+
+/// Luminance and colour of a frame sampled on a strided grid
+pub struct SampledFrame {
+    pub width: usize,
+    pub height: usize,
+    pub luma: Vec<u8>,
+    pub rgb: Vec<[u8; 3]>,
+}
+
+impl SampledFrame {
+    /// Sample every `step`th pixel of every `step`th row, where `step` is chosen so the
+    /// grid is about `target_width` wide. Rows are processed in parallel and the source
+    /// buffer is read in place.
+    fn from_frame(frame: &Frame, target_width: u32) -> Option<Self> {
+        let src_width = frame.width as usize;
+        let src_height = frame.height as usize;
+        let stride = src_width * 3;
+        if src_width == 0 || src_height == 0 || frame.data.len() < stride * src_height {
+            return None;
+        }
+
+        let step = (src_width / target_width.max(1) as usize).max(1);
+        let width = src_width.div_ceil(step);
+        let height = src_height.div_ceil(step);
+
+        let mut luma = vec![0u8; width * height];
+        let mut rgb = vec![[0u8; 3]; width * height];
+        luma.par_chunks_mut(width)
+            .zip(rgb.par_chunks_mut(width))
+            .enumerate()
+            .for_each(|(y, (luma_row, rgb_row))| {
+                let offset = y * step * stride;
+                let src_row = &frame.data[offset..offset + stride];
+                for (x, (luma_out, rgb_out)) in
+                    luma_row.iter_mut().zip(rgb_row.iter_mut()).enumerate()
+                {
+                    let i = x * step * 3;
+                    let pixel = [src_row[i], src_row[i + 1], src_row[i + 2]];
+                    *luma_out = luminance(pixel[0], pixel[1], pixel[2]);
+                    *rgb_out = pixel;
+                }
+            });
+
+        Some(Self {
+            width,
+            height,
+            luma,
+            rgb,
+        })
+    }
+
+    pub fn pixel_count(&self) -> usize {
+        self.width * self.height
+    }
+
+    fn histogram(&self) -> [u32; 256] {
+        let mut histogram = [0u32; 256];
+        for &value in &self.luma {
+            histogram[value as usize] += 1;
+        }
+        histogram
+    }
+}
+
+/// Mean luma (0-1) from a sample's histogram
+pub fn average_brightness(sample: &SampledFrame, histogram: &[u32; 256]) -> f64 {
+    let total_brightness: u64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(value, &count)| value as u64 * count as u64)
+        .sum();
+    (total_brightness as f64 / sample.pixel_count() as f64) / 255.0
+}
+
+/// Rec. 601 luma in fixed point
+pub fn luminance(r: u8, g: u8, b: u8) -> u8 {
+    ((77 * r as u32 + 150 * g as u32 + 29 * b as u32) >> 8) as u8
+}
+
+/// Silence spans from audio levels, as the live pipeline reports them
+pub struct SilenceDetector {
+    threshold: f64,
+    min_duration: f64,
+    /// Set once a real audio level arrives, silence spans are only reported after that
+    has_audio: bool,
+    current_start: Option<f64>,
+    spans: Vec<SilenceSpan>,
+}
+
+impl SilenceDetector {
+    pub fn new(threshold: f64, min_duration: f64) -> Self {
+        Self {
+            threshold,
+            min_duration,
+            has_audio: false,
+            current_start: None,
+            spans: Vec::new(),
+        }
+    }
+
+    /// Feed one audio level sample, returns whether the sample is silent
+    pub fn update(&mut self, time: f64, level: f64) -> bool {
+        if level < self.threshold {
+            if self.current_start.is_none() {
+                self.current_start = Some(time);
+            }
+            true
+        } else {
+            if let Some(start) = self.current_start.take() {
+                if time - start >= self.min_duration {
+                    self.spans.push(SilenceSpan { start, end: time });
+                }
+            }
+            false
+        }
+    }
+
+    /// Completed spans, plus the one still open at `end` if it is long enough
+    pub fn spans(&self, end: f64) -> Vec<SilenceSpan> {
+        let mut spans = self.spans.clone();
+        if let Some(start) = self.current_start {
+            if end - start >= self.min_duration {
+                spans.push(SilenceSpan { start, end });
+            }
+        }
+        spans
+    }
+}
+
+/// Silence is only detected from real audio, never guessed from the picture
+impl FrameAnalyzer for SilenceDetector {
+    fn name(&self) -> &str {
+        "silence"
+    }
+
+    fn process_frame(&mut self, context: &FrameContext, output: &mut AnalyzerOutput) {
+        let silent = match context.audio_level {
+            Some(level) => {
+                self.has_audio = true;
+                self.update(context.time, level)
+            }
+            None => false,
+        };
+        output.value("audioLevel", context.audio_level);
+        output.value("silent", silent);
+    }
+}
+
+impl AnalyticsPipeline {
+    pub fn new(
+        config: AnalyticsConfig,
+        bitrate_kbps: u32,
+        video_path: &Path,
+        encryption: Option<Arc<RecordingKey>>,
+    ) -> Result<Self> {
+        let mut store_path = video_path.to_path_buf();
+        store_path.set_extension("timeline.jsonl");
+
+        let mut pipeline = Self {
+            timeline_data: Arc::new(Mutex::new(TimelineView {
+                downsampled: DownsampledTimeline::new(TIMELINE_VIEW_CAPACITY),
+                recent: RecentEntries::new(),
+            })),
+            timeline_store: TimelineStore::create(store_path, encryption.clone())?,
+            store_failed: false,
+            events: Vec::new(),
+            analyze_every_n_frames: config.analyze_every_n_frames.max(1) as u64,
+            frame_index: 0,
+            brightness: BrightnessAnalyzer,
+            dominance: DominanceAnalyzer,
+            scene: SceneChangeAnalyzer::default(),
+            motion: MotionHeatmap::new(
+                config.heatmap_columns,
+                config.heatmap_rows,
+                config.heatmap_window_seconds,
+            ),
+            idle: IdleDetector::new(config.idle_threshold, config.idle_min_duration),
+            quality: QualityChecker::new(config.quality.clone()),
+            sharpness: SharpnessAnalyzer::new(bitrate_kbps),
+            silence: SilenceDetector::new(config.silence_threshold, config.silence_min_duration),
+            analyzers: Vec::new(),
+            chapters: ChapterDetector::new(config.chapters.clone()),
+            thumbnails: ThumbnailStrip::new(config.thumbnails.clone()),
+            markers: Vec::new(),
+            pending_markers: Vec::new(),
+            bitrate_kbps,
+            warnings: Vec::new(),
+            last_timestamp: 0.0,
+            source: None,
+            video_path: None,
+            encryption,
+            config: config.clone(),
+        };
+
+        for analyzer in pipeline.all_analyzers() {
+            analyzer.configure(&config);
+        }
+        Ok(pipeline)
+    }
+
+    /// Add an analyzer that runs on every analyzed frame, after the built-in ones
+    #[allow(dead_code)] // Extension point, the app itself only uses the built-ins
+    pub fn register(&mut self, mut analyzer: Box<dyn FrameAnalyzer>) {
+        analyzer.configure(&self.config);
+        tracing::debug!("Registered frame analyzer {}", analyzer.name());
+        self.analyzers.push(analyzer);
+    }
+
+    /// Built-ins in dependency order, then the registered analyzers
+    fn all_analyzers(&mut self) -> impl Iterator<Item = &mut dyn FrameAnalyzer> + '_ {
+        let builtins: [&mut dyn FrameAnalyzer; 8] = [
+            &mut self.brightness,
+            &mut self.dominance,
+            &mut self.scene,
+            &mut self.motion,
+            &mut self.idle,
+            &mut self.quality,
+            &mut self.sharpness,
+            &mut self.silence,
+        ];
+        builtins
+            .into_iter()
+            .chain(self.analyzers.iter_mut().map(|a| a.as_mut() as &mut dyn FrameAnalyzer))
+    }
+
+    /// Analyze a frame captured at `time` seconds into the session (pauses excluded).
+    /// `audio_level` is the RMS level (0-1) captured alongside it, `None` when the
+    /// recording has no audio.
+    pub async fn process_frame(
+        &mut self,
+        frame: &Frame,
+        time: f64,
+        audio_level: Option<f64>,
+    ) -> FrameSummary {
+        let index = self.frame_index;
+        self.frame_index += 1;
+        self.last_timestamp = time;
+        if !index.is_multiple_of(self.analyze_every_n_frames) {
+            return self.summary();
+        }
+
+        let Some(sample) = SampledFrame::from_frame(frame, self.config.analysis_width) else {
+            return self.summary();
+        };
+        let histogram = sample.histogram();
+        let colors = color::analyze(&sample, self.config.palette_size);
+
+        let mut output = AnalyzerOutput::default();
+        let context = FrameContext {
+            time,
+            frame,
+            sample: &sample,
+            histogram: &histogram,
+            colors: &colors,
+            audio_level,
+        };
+        for analyzer in self.all_analyzers() {
+            analyzer.process_frame(&context, &mut output);
+        }
+        let scene_change = output.events.iter().any(|e| e.kind == "scene_change");
+        if scene_change {
+            self.chapters.scene_change(time);
+        }
+        self.thumbnails.update(frame, time, scene_change);
+        for marker in self.pending_markers.drain(..) {
+            output.event(
+                "user",
+                "marker",
+                json!({
+                    "label": marker.label,
+                    "kind": marker.kind,
+                    "markerTime": marker.time,
+                }),
+            );
+        }
+
+        let warnings: Vec<AnalyticsWarning> = output
+            .warnings
+            .drain(..)
+            .map(|message| AnalyticsWarning { time, message })
+            .collect();
+        self.warnings.extend(warnings.iter().cloned());
+
+        let entry = TimelineEntry {
+            timestamp: time,
+            palette: colors.palette,
+            values: output.values,
+            events: output.events,
+        };
+
+        let json = entry.to_json();
+        for event in &entry.events {
+            let mut value = json!(event);
+            value["time"] = json!(time);
+            self.events.push(value);
+        }
+        if let Err(e) = self.timeline_store.append(&json) {
+            // Keep recording, the sidecar falls back to the in-memory view
+            if !self.store_failed {
+                tracing::error!("Failed to write timeline store: {}", e);
+                self.store_failed = true;
+            }
+        }
+
+        let mut view = self.timeline_data.lock().await;
+        view.downsampled.push(json.clone());
+        view.recent.push(json);
+
+        FrameSummary {
+            idle: self.idle.is_idle(),
+            warnings,
+        }
+    }
+
+    fn summary(&self) -> FrameSummary {
+        FrameSummary {
+            idle: self.idle.is_idle(),
+            warnings: Vec::new(),
+        }
+    }
+
+    pub async fn save_metadata(&mut self, video_path: &PathBuf) -> Result<()> {
+        let end = self.last_timestamp;
+        let analyzers: serde_json::Map<String, Value> = self
+            .all_analyzers()
+            .map(|a| (a.name().to_string(), a.finish(end)))
+            .filter(|(_, summary)| !summary.is_null())
+            .collect();
+
+        let mut heatmap_path = video_path.clone();
+        heatmap_path.set_extension("heatmap.png");
+        if let Err(e) = self.motion.save_png(&heatmap_path, self.encryption.as_deref()) {
+            tracing::warn!("Failed to save motion heatmap: {}", e);
+        }
+
+        let thumbnails = self.thumbnails.save(video_path, self.encryption.as_deref()).unwrap_or_else(|e| {
+            tracing::warn!("Failed to save thumbnails: {}", e);
+            Value::Null
+        });
+
+        let mut motion_heatmap = self.motion.to_json(None, None);
+        motion_heatmap["png"] = json!(heatmap_path.to_string_lossy());
+        motion_heatmap["windows"] = json!(self.motion.windows());
+
+        let metadata = json!({
+            "video_path": self.video_path.as_ref().unwrap_or(video_path).to_string_lossy(),
+            "duration": self.last_timestamp,
+            "source": self.source,
+            "events": self.events,
+            "analyzers": analyzers,
+            "audio": self.silence.has_audio,
+            "silence_spans": self.silence.spans(self.last_timestamp),
+            "idle_spans": self.idle.spans(self.last_timestamp),
+            "motion_heatmap": motion_heatmap,
+            "quality": self.quality.summary(self.last_timestamp),
+            "chapters": self.chapters(),
+            "markers": self.markers,
+            "thumbnails": thumbnails,
+            "bitrate_kbps": self.bitrate_kbps,
+            "warnings": self.warnings,
+        });
+
+        // Save to sidecar file
+        let mut meta_path = video_path.clone();
+        meta_path.set_extension("meta.json");
+        self.write_sidecar(&meta_path, &metadata).await?;
+
+        tracing::info!(
+            "Saved {} timeline entries to {:?}",
+            self.timeline_store.len(),
+            meta_path
+        );
+        if !self.store_failed {
+            let _ = std::fs::remove_file(self.timeline_store.path());
+        }
+
+        Ok(())
+    }
+
+    pub fn set_source(&mut self, source: String) {
+        self.source = Some(source);
+    }
+
+    pub fn set_video_path(&mut self, video_path: PathBuf) {
+        self.video_path = Some(video_path);
+    }
+
+    /// Drop a marker at the session time of the latest frame, which already excludes
+    /// manual and idle pauses. It shows up on the next analyzed timeline entry.
+    pub fn add_marker(&mut self, label: String, kind: String) -> Marker {
+        let marker = Marker {
+            time: self.last_timestamp,
+            label,
+            kind,
+        };
+        tracing::info!("Marker {:?} at {:.1}s", marker.label, marker.time);
+        self.markers.push(marker.clone());
+        self.pending_markers.push(marker.clone());
+        marker
+    }
+
+    /// Chapters derived from scene changes so far, split at every marker
+    pub fn chapters(&self) -> Vec<Chapter> {
+        let mut chapters = self.chapters.chapters(self.last_timestamp);
+        for marker in &self.markers {
+            chapters::split(&mut chapters, marker.time, marker.label.clone());
+        }
+        chapters
+    }
+
+    /// Drop the partial on-disk timeline without writing a sidecar
+    pub fn discard(&mut self) {
+        let _ = std::fs::remove_file(self.timeline_store.path());
+    }
+
+    /// Write the sidecar with `entries` streamed from the on-disk store, so a long
+    /// recording's timeline never has to be held in memory at once
+    async fn write_sidecar(&mut self, meta_path: &Path, metadata: &Value) -> Result<()> {
+        if self.store_failed {
+            let view = self.timeline_data.lock().await;
+            let entries = view.downsampled.iter().map(|e| Ok(e.to_string()));
+            store::write_sidecar(meta_path, metadata, entries, self.encryption.as_deref())
+        } else {
+            let lines = self.timeline_store.lines()?.map(|line| Ok(line?));
+            store::write_sidecar(meta_path, metadata, lines, self.encryption.as_deref())
+        }
+    }
+
+    /// Flush the on-disk timeline so it survives a crash, returns the entries written
+    pub fn checkpoint(&mut self) -> Result<u64> {
+        self.timeline_store.flush()?;
+        Ok(self.timeline_store.len())
+    }
+
+    /// What `get_timeline_data` needs, so the query runs without the pipeline's lock
+    pub fn timeline_reader(&mut self) -> TimelineReader {
+        let store = if self.store_failed {
+            None
+        } else {
+            self.timeline_store
+                .reader()
+                .inspect_err(|e| tracing::warn!("Failed to read timeline store: {}", e))
+                .ok()
+        };
+        TimelineReader {
+            view: self.timeline_data.clone(),
+            store,
+            end: self.last_timestamp,
+        }
+    }
+
+    /// Full resolution entries between `from` and `to` seconds, e.g. for a replay clip
+    pub async fn timeline_slice(&mut self, from: f64, to: f64) -> Vec<Value> {
+        let query = TimelineQuery {
+            from: Some(from),
+            to: Some(to),
+            ..TimelineQuery::default()
+        };
+        if !self.store_failed {
+            match self.read_store_range(&query) {
+                Ok(entries) => return entries,
+                Err(e) => tracing::warn!("Failed to read timeline store: {}", e),
+            }
+        }
+        let view = self.timeline_data.lock().await;
+        view.downsampled
+            .iter()
+            .filter(|e| query.contains(e["time"].as_f64().unwrap_or(0.0)))
+            .cloned()
+            .collect()
+    }
+
+    fn read_store_range(&mut self, query: &TimelineQuery) -> Result<Vec<Value>> {
+        self.timeline_store.range(query.from, query.to)
+    }
+
+    /// Motion heatmap covering `[from, to]` seconds, or the whole session so far
+    pub fn get_motion_heatmap(&self, from: Option<f64>, to: Option<f64>) -> Value {
+        self.motion.to_json(from, to)
+    }
+}
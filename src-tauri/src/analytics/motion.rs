@@ -0,0 +1,161 @@
+use super::analyzer::{AnalyzerOutput, FrameAnalyzer, FrameContext};
+use super::SampledFrame;
+use crate::encryption::{self, RecordingKey};
+use anyhow::Result;
+use image::{ImageFormat, Rgba, RgbaImage};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::path::Path;
+
+/// Motion energy accumulated over one slice of the recording
+#[derive(Clone, Serialize)]
+pub struct HeatmapWindow {
+    pub start: f64,
+    pub end: f64,
+    /// Row-major, `columns * rows` tiles of summed mean absolute luma difference
+    pub grid: Vec<f64>,
+}
+
+/// Accumulates frame-to-frame differences per screen tile across a recording
+pub struct MotionHeatmap {
+    columns: usize,
+    rows: usize,
+    window_seconds: f64,
+    last: Option<(usize, usize, Vec<u8>)>,
+    windows: Vec<HeatmapWindow>,
+}
+
+impl MotionHeatmap {
+    pub fn new(columns: usize, rows: usize, window_seconds: f64) -> Self {
+        Self {
+            columns: columns.max(1),
+            rows: rows.max(1),
+            window_seconds: window_seconds.max(1.0),
+            last: None,
+            windows: Vec::new(),
+        }
+    }
+
+    /// Diff against the previous sample and add it to the heatmap. Returns the mean
+    /// absolute luma difference over the whole frame (0-1).
+    pub fn update(&mut self, sample: &SampledFrame, time: f64) -> f64 {
+        let current = (sample.width, sample.height, sample.luma.clone());
+        let Some((width, height, last)) = self.last.replace(current) else {
+            return 0.0;
+        };
+        if width != sample.width || height != sample.height {
+            return 0.0;
+        }
+
+        let mut energy = vec![0.0; self.columns * self.rows];
+        let mut counts = vec![0u32; self.columns * self.rows];
+        let mut total = 0u64;
+        for y in 0..height {
+            let tile_row = y * self.rows / height * self.columns;
+            let offset = y * width;
+            for x in 0..width {
+                let diff = last[offset + x].abs_diff(sample.luma[offset + x]);
+                let tile = tile_row + x * self.columns / width;
+                energy[tile] += diff as f64;
+                counts[tile] += 1;
+                total += diff as u64;
+            }
+        }
+
+        let needs_window = self
+            .windows
+            .last()
+            .map(|w| time >= w.start + self.window_seconds)
+            .unwrap_or(true);
+        if needs_window {
+            self.windows.push(HeatmapWindow {
+                start: time,
+                end: time,
+                grid: vec![0.0; self.columns * self.rows],
+            });
+        }
+
+        let window = self.windows.last_mut().unwrap();
+        window.end = time;
+        for ((cell, sum), count) in window.grid.iter_mut().zip(&energy).zip(&counts) {
+            if *count > 0 {
+                *cell += sum / (*count as f64 * 255.0);
+            }
+        }
+
+        total as f64 / (sample.pixel_count() as f64 * 255.0)
+    }
+
+    /// Sum of every window overlapping `[from, to]`; open ends cover the whole recording
+    pub fn grid(&self, from: Option<f64>, to: Option<f64>) -> Vec<f64> {
+        let from = from.unwrap_or(f64::MIN);
+        let to = to.unwrap_or(f64::MAX);
+        let mut grid = vec![0.0; self.columns * self.rows];
+        for window in self
+            .windows
+            .iter()
+            .filter(|w| w.end >= from && w.start <= to)
+        {
+            for (cell, value) in grid.iter_mut().zip(&window.grid) {
+                *cell += value;
+            }
+        }
+        grid
+    }
+
+    pub fn to_json(&self, from: Option<f64>, to: Option<f64>) -> Value {
+        json!({
+            "columns": self.columns,
+            "rows": self.rows,
+            "window_seconds": self.window_seconds,
+            "grid": self.grid(from, to),
+        })
+    }
+
+    pub fn windows(&self) -> &[HeatmapWindow] {
+        &self.windows
+    }
+
+    /// Render the whole-recording heatmap as a translucent overlay, sized like the
+    /// analysis grid so it can be stretched over the video. Encrypted with `key` if set.
+    pub fn save_png(&self, path: &Path, key: Option<&RecordingKey>) -> Result<()> {
+        let (width, height) = self
+            .last
+            .as_ref()
+            .map(|(w, h, _)| (*w as u32, *h as u32))
+            .unwrap_or((self.columns as u32, self.rows as u32));
+
+        let grid = self.grid(None, None);
+        let peak = grid.iter().cloned().fold(0.0, f64::max);
+
+        let image = RgbaImage::from_fn(width, height, |x, y| {
+            let tile = (y as usize * self.rows / height as usize) * self.columns
+                + x as usize * self.columns / width as usize;
+            let value = if peak > 0.0 { grid[tile] / peak } else { 0.0 };
+            heat_color(value)
+        });
+        let mut png = std::io::Cursor::new(Vec::new());
+        image.write_to(&mut png, ImageFormat::Png)?;
+        encryption::write_file(path, png.get_ref(), key)
+    }
+}
+
+/// Blue (cold) through yellow to red (hot), fading to transparent where nothing moved
+fn heat_color(value: f64) -> Rgba<u8> {
+    let v = value.clamp(0.0, 1.0);
+    let r = (255.0 * (2.0 * v).min(1.0)) as u8;
+    let g = (255.0 * (1.0 - (2.0 * v - 1.0).abs())) as u8;
+    let b = (255.0 * (1.0 - 2.0 * v).max(0.0)) as u8;
+    let a = (200.0 * v.sqrt()) as u8;
+    Rgba([r, g, b, a])
+}
+
+impl FrameAnalyzer for MotionHeatmap {
+    fn name(&self) -> &str {
+        "motion"
+    }
+
+    fn process_frame(&mut self, context: &FrameContext, output: &mut AnalyzerOutput) {
+        output.series("motion", self.update(context.sample, context.time));
+    }
+}
@@ -0,0 +1,260 @@
+use super::analyzer::{AnalyzerOutput, FrameAnalyzer, FrameContext};
+use crate::capture::Frame;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QualityIssue {
+    BlackFrame,
+    WhiteFrame,
+    /// Bit-identical frames right after the screen was active, usually a stalled capture
+    Frozen,
+    Flicker,
+}
+
+const ISSUES: [QualityIssue; 4] = [
+    QualityIssue::BlackFrame,
+    QualityIssue::WhiteFrame,
+    QualityIssue::Frozen,
+    QualityIssue::Flicker,
+];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QualityConfig {
+    /// Share of pixels (0-1) that must be near black or white for a blank frame
+    pub blank_fraction: f64,
+    /// Seconds of identical frames before they count as frozen
+    pub frozen_min_duration: f64,
+    /// Recent motion (0-1) above which a sudden freeze is suspicious
+    pub expected_motion: f64,
+    /// Brightness swing (0-1) between frames that counts towards flicker
+    pub flicker_delta: f64,
+    /// Sliding window in seconds for counting flicker reversals
+    pub flicker_window: f64,
+    /// Brightness direction reversals inside the window that count as flicker
+    pub flicker_min_reversals: usize,
+}
+
+impl Default for QualityConfig {
+    fn default() -> Self {
+        Self {
+            blank_fraction: 0.98,
+            frozen_min_duration: 3.0,
+            expected_motion: 0.005,
+            flicker_delta: 0.2,
+            flicker_window: 1.0,
+            flicker_min_reversals: 3,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct QualityEvent {
+    pub kind: QualityIssue,
+    pub start: f64,
+    pub end: f64,
+}
+
+pub struct QualityChecker {
+    config: QualityConfig,
+    /// Exponential moving average of frame motion
+    activity: f64,
+    /// Content hash of the previous frame
+    last_digest: Option<u64>,
+    /// Start of the current run of identical frames, and whether motion was expected then
+    identical_since: Option<(f64, bool)>,
+    brightness_history: VecDeque<(f64, f64)>,
+    open: [Option<f64>; 4],
+    events: Vec<QualityEvent>,
+}
+
+impl QualityChecker {
+    pub fn new(config: QualityConfig) -> Self {
+        Self {
+            config,
+            activity: 0.0,
+            last_digest: None,
+            identical_since: None,
+            brightness_history: VecDeque::new(),
+            open: [None; 4],
+            events: Vec::new(),
+        }
+    }
+
+    /// Check one analyzed frame, returns the issues present on it
+    pub fn update(
+        &mut self,
+        time: f64,
+        frame: &Frame,
+        histogram: &[u32; 256],
+        pixel_count: usize,
+        brightness: f64,
+        motion: f64,
+    ) -> Vec<QualityIssue> {
+        let mut active = Vec::new();
+
+        let pixels = pixel_count.max(1) as f64;
+        let dark = histogram[..16].iter().sum::<u32>() as f64 / pixels;
+        let light = histogram[240..].iter().sum::<u32>() as f64 / pixels;
+        if dark >= self.config.blank_fraction {
+            active.push(QualityIssue::BlackFrame);
+        } else if light >= self.config.blank_fraction {
+            active.push(QualityIssue::WhiteFrame);
+        }
+
+        // Sampled motion can be zero while a cursor blinks, so compare rows at full width.
+        // The first frame has no previous digest to match.
+        let digest = digest(frame);
+        if self.last_digest.replace(digest) == Some(digest) {
+            let (since, expected) = *self
+                .identical_since
+                .get_or_insert((time, self.activity >= self.config.expected_motion));
+            if expected && time - since >= self.config.frozen_min_duration {
+                active.push(QualityIssue::Frozen);
+            }
+        } else {
+            self.identical_since = None;
+            self.activity = 0.9 * self.activity + 0.1 * motion;
+        }
+
+        if self.update_flicker(time, brightness) {
+            active.push(QualityIssue::Flicker);
+        }
+
+        for (slot, issue) in ISSUES.iter().enumerate() {
+            let is_active = active.contains(issue);
+            match self.open[slot] {
+                None if is_active => {
+                    // Frozen spans start where the identical frames began
+                    let start = match issue {
+                        QualityIssue::Frozen => {
+                            self.identical_since.map(|(t, _)| t).unwrap_or(time)
+                        }
+                        _ => time,
+                    };
+                    self.open[slot] = Some(start);
+                    tracing::warn!("Capture quality issue {:?} at {:.1}s", issue, start);
+                }
+                Some(start) if !is_active => {
+                    self.open[slot] = None;
+                    self.events.push(QualityEvent {
+                        kind: *issue,
+                        start,
+                        end: time,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        active
+    }
+
+    fn update_flicker(&mut self, time: f64, brightness: f64) -> bool {
+        self.brightness_history.push_back((time, brightness));
+        while let Some(&(t, _)) = self.brightness_history.front() {
+            if time - t > self.config.flicker_window {
+                self.brightness_history.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        let deltas: Vec<f64> = self
+            .brightness_history
+            .iter()
+            .zip(self.brightness_history.iter().skip(1))
+            .map(|((_, a), (_, b))| b - a)
+            .filter(|d| d.abs() >= self.config.flicker_delta)
+            .collect();
+        let reversals = deltas
+            .windows(2)
+            .filter(|pair| pair[0].signum() != pair[1].signum())
+            .count();
+
+        reversals >= self.config.flicker_min_reversals
+    }
+
+    /// Completed events, plus the ones still open at `end`
+    pub fn events(&self, end: f64) -> Vec<QualityEvent> {
+        let mut events = self.events.clone();
+        for (slot, issue) in ISSUES.iter().enumerate() {
+            if let Some(start) = self.open[slot] {
+                events.push(QualityEvent {
+                    kind: *issue,
+                    start,
+                    end,
+                });
+            }
+        }
+        events
+    }
+
+    /// Per-issue event counts and total seconds for the sidecar
+    pub fn summary(&self, end: f64) -> Value {
+        let events = self.events(end);
+        let mut summary = serde_json::Map::new();
+        for issue in ISSUES {
+            let matching: Vec<&QualityEvent> = events.iter().filter(|e| e.kind == issue).collect();
+            summary.insert(
+                serde_json::to_value(issue)
+                    .ok()
+                    .and_then(|v| v.as_str().map(str::to_string))
+                    .unwrap_or_default(),
+                json!({
+                    "count": matching.len(),
+                    "seconds": matching.iter().map(|e| e.end - e.start).sum::<f64>(),
+                }),
+            );
+        }
+        json!({
+            "events": events,
+            "summary": summary,
+        })
+    }
+}
+
+/// Runs after the brightness and motion analyzers and reads their values
+impl FrameAnalyzer for QualityChecker {
+    fn name(&self) -> &str {
+        "quality"
+    }
+
+    fn process_frame(&mut self, context: &FrameContext, output: &mut AnalyzerOutput) {
+        let issues = self.update(
+            context.time,
+            context.frame,
+            context.histogram,
+            context.sample.pixel_count(),
+            output.number("brightness").unwrap_or(0.0),
+            output.number("motion").unwrap_or(0.0),
+        );
+        output.value("quality", issues);
+    }
+}
+
+/// Every this many rows go into a frame's digest, closer than a text cursor is tall
+const DIGEST_ROW_STRIDE: usize = 4;
+
+/// Content hash of every `DIGEST_ROW_STRIDE`th row, hashed in parallel
+fn digest(frame: &Frame) -> u64 {
+    let row_len = (frame.width as usize * 3).max(1);
+    let slices: Vec<u64> = frame
+        .data
+        .par_chunks(row_len)
+        .step_by(DIGEST_ROW_STRIDE)
+        .map(|slice| {
+            let mut hasher = DefaultHasher::new();
+            slice.hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect();
+    let mut hasher = DefaultHasher::new();
+    (frame.width, frame.height, slices).hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,249 @@
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, VecDeque};
+
+/// Full resolution entries kept in memory for incremental polling
+const RECENT_CAPACITY: usize = 2000;
+
+/// Parameters accepted by `get_timeline_data`. With `since` set, full resolution entries
+/// after that cursor are returned; otherwise `[from, to]` is bucketed down to `max_points`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TimelineQuery {
+    pub from: Option<f64>,
+    pub to: Option<f64>,
+    pub max_points: Option<usize>,
+    /// Only these series (plus `time`) are returned, all of them when unset
+    pub series: Option<Vec<String>>,
+    pub since: Option<u64>,
+}
+
+impl TimelineQuery {
+    pub fn contains(&self, time: f64) -> bool {
+        self.from.is_none_or(|from| time >= from) && self.to.is_none_or(|to| time <= to)
+    }
+}
+
+/// Recently added entries, each tagged with its position in the full timeline
+pub struct RecentEntries {
+    entries: VecDeque<(u64, Value)>,
+    next_index: u64,
+}
+
+impl RecentEntries {
+    pub fn new() -> Self {
+        Self {
+            entries: VecDeque::with_capacity(RECENT_CAPACITY),
+            next_index: 0,
+        }
+    }
+
+    pub fn push(&mut self, entry: Value) {
+        self.entries.push_back((self.next_index, entry));
+        self.next_index += 1;
+        if self.entries.len() > RECENT_CAPACITY {
+            self.entries.pop_front();
+        }
+    }
+
+    /// Entries at or after `cursor`, the next cursor, and whether older entries were
+    /// already evicted
+    pub fn since(&self, cursor: u64, query: &TimelineQuery) -> Value {
+        let oldest = self
+            .entries
+            .front()
+            .map(|(i, _)| *i)
+            .unwrap_or(self.next_index);
+        let entries: Vec<Value> = self
+            .entries
+            .iter()
+            .filter(|(i, _)| *i >= cursor)
+            .map(|(_, e)| select_series(e, query.series.as_deref()))
+            .collect();
+
+        json!({
+            "entries": entries,
+            "cursor": self.next_index,
+            "truncated": cursor < oldest,
+        })
+    }
+
+    pub fn cursor(&self) -> u64 {
+        self.next_index
+    }
+}
+
+/// Reduce `entries` to at most `max_points` equal-time buckets. Numeric series become
+/// the bucket average with `<name>Min`/`<name>Max` alongside, booleans are true if
+/// any entry in the bucket was, and events are concatenated.
+pub fn bucket(entries: Vec<Value>, max_points: usize, series: Option<&[String]>) -> Vec<Value> {
+    let entries: Vec<Value> = entries.iter().map(|e| select_series(e, series)).collect();
+    if max_points == 0 || entries.len() <= max_points {
+        return entries;
+    }
+
+    let time = |e: &Value| e["time"].as_f64().unwrap_or(0.0);
+    let start = entries.first().map(time).unwrap_or(0.0);
+    let end = entries.last().map(time).unwrap_or(0.0);
+    let width = ((end - start) / max_points as f64).max(f64::EPSILON);
+
+    let mut buckets: Vec<Vec<&Value>> = vec![Vec::new(); max_points];
+    for entry in &entries {
+        let index = (((time(entry) - start) / width) as usize).min(max_points - 1);
+        buckets[index].push(entry);
+    }
+
+    buckets
+        .into_iter()
+        .filter(|b| !b.is_empty())
+        .map(|b| merge_bucket(&b))
+        .collect()
+}
+
+/// Running sum, count, min and max of one numeric series inside a bucket
+struct SeriesStats {
+    sum: f64,
+    count: u32,
+    min: f64,
+    max: f64,
+}
+
+/// One bucket merged as its entries arrive, without keeping them
+#[derive(Default)]
+struct BucketStats {
+    entries: usize,
+    stats: BTreeMap<String, SeriesStats>,
+    merged: Map<String, Value>,
+    events: Vec<Value>,
+}
+
+impl BucketStats {
+    fn add(&mut self, entry: &Value) {
+        self.entries += 1;
+        let Some(fields) = entry.as_object() else {
+            return;
+        };
+        for (key, value) in fields {
+            match value {
+                Value::Number(n) => {
+                    let v = n.as_f64().unwrap_or(0.0);
+                    let s = self.stats.entry(key.clone()).or_insert(SeriesStats {
+                        sum: 0.0,
+                        count: 0,
+                        min: v,
+                        max: v,
+                    });
+                    s.sum += v;
+                    s.count += 1;
+                    s.min = s.min.min(v);
+                    s.max = s.max.max(v);
+                }
+                Value::Bool(b) => {
+                    let any = self
+                        .merged
+                        .get(key)
+                        .and_then(Value::as_bool)
+                        .unwrap_or(false);
+                    self.merged.insert(key.clone(), json!(any || *b));
+                }
+                Value::Array(items) if key == "events" => self.events.extend(items.iter().cloned()),
+                _ => {}
+            }
+        }
+    }
+
+    fn finish(self) -> Value {
+        let mut merged = self.merged;
+        for (key, s) in self.stats {
+            merged.insert(key.clone(), json!(s.sum / s.count as f64));
+            if key != "time" {
+                merged.insert(format!("{}Min", key), json!(s.min));
+                merged.insert(format!("{}Max", key), json!(s.max));
+            }
+        }
+        if !self.events.is_empty() {
+            merged.insert("events".to_string(), Value::Array(self.events));
+        }
+        Value::Object(merged)
+    }
+}
+
+fn merge_bucket(bucket: &[&Value]) -> Value {
+    let mut stats = BucketStats::default();
+    for entry in bucket {
+        stats.add(entry);
+    }
+    stats.finish()
+}
+
+/// [`bucket`] for entries streamed in time order over `[start, end]`, so a long range is
+/// never held in memory. Entries are kept as they are until there are more than
+/// `max_points`.
+pub struct StreamingBuckets {
+    start: f64,
+    width: f64,
+    max_points: usize,
+    series: Option<Vec<String>>,
+    entries: Vec<Value>,
+    buckets: Vec<BucketStats>,
+}
+
+impl StreamingBuckets {
+    pub fn new(start: f64, end: f64, max_points: usize, series: Option<Vec<String>>) -> Self {
+        Self {
+            start,
+            width: ((end - start) / max_points.max(1) as f64).max(f64::EPSILON),
+            max_points,
+            series,
+            entries: Vec::new(),
+            buckets: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, entry: Value) {
+        let entry = select_series(&entry, self.series.as_deref());
+        if !self.buckets.is_empty() {
+            self.add(&entry);
+            return;
+        }
+        self.entries.push(entry);
+        if self.max_points > 0 && self.entries.len() > self.max_points {
+            self.buckets = (0..self.max_points)
+                .map(|_| BucketStats::default())
+                .collect();
+            for entry in std::mem::take(&mut self.entries) {
+                self.add(&entry);
+            }
+        }
+    }
+
+    fn add(&mut self, entry: &Value) {
+        let time = entry["time"].as_f64().unwrap_or(0.0);
+        let index = (((time - self.start) / self.width) as usize).min(self.max_points - 1);
+        self.buckets[index].add(entry);
+    }
+
+    pub fn finish(self) -> Vec<Value> {
+        if self.buckets.is_empty() {
+            return self.entries;
+        }
+        self.buckets
+            .into_iter()
+            .filter(|b| b.entries > 0)
+            .map(BucketStats::finish)
+            .collect()
+    }
+}
+
+fn select_series(entry: &Value, series: Option<&[String]>) -> Value {
+    let (Some(series), Some(fields)) = (series, entry.as_object()) else {
+        return entry.clone();
+    };
+    Value::Object(
+        fields
+            .iter()
+            .filter(|(key, _)| key.as_str() == "time" || series.iter().any(|s| s == *key))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect(),
+    )
+}
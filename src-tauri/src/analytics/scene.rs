@@ -0,0 +1,282 @@
+use super::SampledFrame;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SceneDetectorKind {
+    /// Global luminance histogram difference
+    Histogram,
+    /// Difference hash (9x8 gradient signs)
+    Dhash,
+    /// DCT-based perceptual hash
+    Phash,
+    /// Mean structural similarity over 8x8 blocks
+    Ssim,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SceneDetectionConfig {
+    pub detector: SceneDetectorKind,
+    /// Summed histogram difference, as a fraction of sampled pixels (0-1)
+    pub histogram_threshold: f64,
+    /// Hamming distance (out of 64 bits) at which a hash counts as a new scene
+    pub hash_threshold: u32,
+    /// Mean block SSIM below which frames count as a new scene
+    pub ssim_threshold: f64,
+}
+
+impl Default for SceneDetectionConfig {
+    fn default() -> Self {
+        Self {
+            detector: SceneDetectorKind::Histogram,
+            histogram_threshold: 0.1,
+            hash_threshold: 12,
+            ssim_threshold: 0.6,
+        }
+    }
+}
+
+pub struct SceneDecision {
+    pub changed: bool,
+    /// 0-1, where 0.5 sits exactly on the detector threshold
+    pub confidence: f64,
+}
+
+impl SceneDecision {
+    fn unchanged() -> Self {
+        Self {
+            changed: false,
+            confidence: 0.0,
+        }
+    }
+
+    /// Build a decision from a normalized distance (0 = identical, 1 = completely different)
+    fn from_distance(distance: f64, threshold: f64) -> Self {
+        let distance = distance.clamp(0.0, 1.0);
+        let threshold = threshold.clamp(f64::EPSILON, 1.0 - f64::EPSILON);
+        if distance >= threshold {
+            Self {
+                changed: true,
+                confidence: 0.5 + 0.5 * (distance - threshold) / (1.0 - threshold),
+            }
+        } else {
+            Self {
+                changed: false,
+                confidence: 0.5 * distance / threshold,
+            }
+        }
+    }
+}
+
+pub trait SceneDetector: Send {
+    fn name(&self) -> &'static str;
+    fn detect(&mut self, sample: &SampledFrame, histogram: &[u32; 256]) -> SceneDecision;
+}
+
+pub fn create_detector(config: &SceneDetectionConfig) -> Box<dyn SceneDetector> {
+    match config.detector {
+        SceneDetectorKind::Histogram => Box::new(HistogramDetector {
+            threshold: config.histogram_threshold,
+            last: None,
+        }),
+        SceneDetectorKind::Dhash => Box::new(HashDetector {
+            name: "dhash",
+            hash: dhash,
+            threshold: config.hash_threshold,
+            last: None,
+        }),
+        SceneDetectorKind::Phash => Box::new(HashDetector {
+            name: "phash",
+            hash: phash,
+            threshold: config.hash_threshold,
+            last: None,
+        }),
+        SceneDetectorKind::Ssim => Box::new(SsimDetector {
+            threshold: config.ssim_threshold,
+            last: None,
+        }),
+    }
+}
+
+struct HistogramDetector {
+    threshold: f64,
+    last: Option<[u32; 256]>,
+}
+
+impl SceneDetector for HistogramDetector {
+    fn name(&self) -> &'static str {
+        "histogram"
+    }
+
+    fn detect(&mut self, sample: &SampledFrame, histogram: &[u32; 256]) -> SceneDecision {
+        let Some(last) = self.last.replace(*histogram) else {
+            return SceneDecision::unchanged();
+        };
+
+        let diff: u64 = histogram
+            .iter()
+            .zip(last.iter())
+            .map(|(a, b)| a.abs_diff(*b) as u64)
+            .sum();
+
+        // Every pixel that moves bucket is counted twice (once out, once in), so the
+        // default threshold of 0.1 fires once 5% of pixels change bucket
+        let distance = (diff as f64 / sample.pixel_count() as f64).min(1.0);
+        SceneDecision::from_distance(distance, self.threshold)
+    }
+}
+
+struct HashDetector {
+    name: &'static str,
+    hash: fn(&SampledFrame) -> u64,
+    threshold: u32,
+    last: Option<u64>,
+}
+
+impl SceneDetector for HashDetector {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn detect(&mut self, sample: &SampledFrame, _histogram: &[u32; 256]) -> SceneDecision {
+        let hash = (self.hash)(sample);
+        let Some(last) = self.last.replace(hash) else {
+            return SceneDecision::unchanged();
+        };
+
+        let distance = (hash ^ last).count_ones() as f64 / 64.0;
+        SceneDecision::from_distance(distance, self.threshold as f64 / 64.0)
+    }
+}
+
+struct SsimDetector {
+    threshold: f64,
+    last: Option<(usize, usize, Vec<u8>)>,
+}
+
+impl SceneDetector for SsimDetector {
+    fn name(&self) -> &'static str {
+        "ssim"
+    }
+
+    fn detect(&mut self, sample: &SampledFrame, _histogram: &[u32; 256]) -> SceneDecision {
+        let current = (sample.width, sample.height, sample.luma.clone());
+        let Some((width, height, last)) = self.last.replace(current) else {
+            return SceneDecision::unchanged();
+        };
+
+        // A resized capture source is always a new scene
+        if width != sample.width || height != sample.height {
+            return SceneDecision::from_distance(1.0, 1.0 - self.threshold);
+        }
+
+        let ssim = block_ssim(&last, &sample.luma, width, height);
+        SceneDecision::from_distance(1.0 - ssim, 1.0 - self.threshold)
+    }
+}
+
+/// Box-filter the sampled luma down to a `width` x `height` grid
+fn resize(sample: &SampledFrame, width: usize, height: usize) -> Vec<f64> {
+    let mut out = vec![0.0; width * height];
+    for (oy, row) in out.chunks_mut(width).enumerate() {
+        let y0 = oy * sample.height / height;
+        let y1 = ((oy + 1) * sample.height / height).max(y0 + 1);
+        for (ox, value) in row.iter_mut().enumerate() {
+            let x0 = ox * sample.width / width;
+            let x1 = ((ox + 1) * sample.width / width).max(x0 + 1);
+            let mut sum = 0u64;
+            for y in y0..y1.min(sample.height) {
+                let line = &sample.luma[y * sample.width..(y + 1) * sample.width];
+                sum += line[x0..x1.min(sample.width)]
+                    .iter()
+                    .map(|&v| v as u64)
+                    .sum::<u64>();
+            }
+            *value = sum as f64 / ((y1 - y0) * (x1 - x0)) as f64;
+        }
+    }
+    out
+}
+
+fn dhash(sample: &SampledFrame) -> u64 {
+    let grid = resize(sample, 9, 8);
+    let mut hash = 0u64;
+    for row in grid.chunks(9) {
+        for pair in row.windows(2) {
+            hash = (hash << 1) | (pair[0] < pair[1]) as u64;
+        }
+    }
+    hash
+}
+
+fn phash(sample: &SampledFrame) -> u64 {
+    const N: usize = 32;
+    let grid = resize(sample, N, N);
+
+    // Only the low 8x8 frequencies are needed
+    let cos = |k: usize, n: usize| {
+        (std::f64::consts::PI * (2 * n + 1) as f64 * k as f64 / (2 * N) as f64).cos()
+    };
+    let mut rows = vec![0.0; 8 * N];
+    for y in 0..N {
+        for u in 0..8 {
+            rows[u * N + y] = (0..N).map(|x| grid[y * N + x] * cos(u, x)).sum();
+        }
+    }
+    let mut coefficients = [0.0; 64];
+    for v in 0..8 {
+        for u in 0..8 {
+            coefficients[v * 8 + u] = (0..N).map(|y| rows[u * N + y] * cos(v, y)).sum();
+        }
+    }
+
+    // Median of the AC terms, skipping the DC coefficient
+    let mut sorted = coefficients[1..].to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let median = sorted[sorted.len() / 2];
+
+    coefficients
+        .iter()
+        .fold(0u64, |hash, &c| (hash << 1) | (c > median) as u64)
+}
+
+/// Mean SSIM over non-overlapping 8x8 blocks
+fn block_ssim(a: &[u8], b: &[u8], width: usize, height: usize) -> f64 {
+    const BLOCK: usize = 8;
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+    let mut total = 0.0;
+    let mut blocks = 0usize;
+    for by in (0..height.saturating_sub(BLOCK - 1)).step_by(BLOCK) {
+        for bx in (0..width.saturating_sub(BLOCK - 1)).step_by(BLOCK) {
+            let (mut sa, mut sb, mut saa, mut sbb, mut sab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            for y in by..by + BLOCK {
+                for x in bx..bx + BLOCK {
+                    let va = a[y * width + x] as f64;
+                    let vb = b[y * width + x] as f64;
+                    sa += va;
+                    sb += vb;
+                    saa += va * va;
+                    sbb += vb * vb;
+                    sab += va * vb;
+                }
+            }
+            let n = (BLOCK * BLOCK) as f64;
+            let (ma, mb) = (sa / n, sb / n);
+            let var_a = saa / n - ma * ma;
+            let var_b = sbb / n - mb * mb;
+            let cov = sab / n - ma * mb;
+            total += ((2.0 * ma * mb + C1) * (2.0 * cov + C2))
+                / ((ma * ma + mb * mb + C1) * (var_a + var_b + C2));
+            blocks += 1;
+        }
+    }
+
+    if blocks == 0 {
+        1.0
+    } else {
+        total / blocks as f64
+    }
+}
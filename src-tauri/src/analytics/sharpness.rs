@@ -0,0 +1,127 @@
+use super::analyzer::{AnalyzerOutput, FrameAnalyzer, FrameContext};
+use super::{luminance, AnalyticsConfig};
+use crate::capture::Frame;
+use rayon::prelude::*;
+
+/// Frame rate the bitrate legibility check assumes, matching the encoder target
+const ASSUMED_FPS: f64 = 30.0;
+
+/// Source sharpness per frame, warning once each time the detail outgrows the bitrate
+pub struct SharpnessAnalyzer {
+    bitrate_kbps: u32,
+    analysis_width: u32,
+    /// Moving average of source sharpness, smooths out single busy frames
+    average: Option<f64>,
+    warned: bool,
+}
+
+impl SharpnessAnalyzer {
+    pub fn new(bitrate_kbps: u32) -> Self {
+        Self {
+            bitrate_kbps,
+            analysis_width: 1,
+            average: None,
+            warned: false,
+        }
+    }
+
+    /// Warn once each time the recording becomes too detailed for the bitrate preset
+    fn check_bitrate(&mut self, frame: &Frame, sharpness: f64) -> Option<String> {
+        let average = match self.average {
+            Some(average) => 0.95 * average + 0.05 * sharpness,
+            None => sharpness,
+        };
+        self.average = Some(average);
+
+        let pixels_per_second = frame.width as f64 * frame.height as f64 * ASSUMED_FPS;
+        if pixels_per_second == 0.0 {
+            return None;
+        }
+        let available = self.bitrate_kbps as f64 * 1000.0 / pixels_per_second;
+        let required = required_bits_per_pixel(average);
+
+        if available >= required {
+            self.warned = false;
+            return None;
+        }
+        if self.warned {
+            return None;
+        }
+        self.warned = true;
+
+        let message = format!(
+            "{} kbps is likely too low for the on-screen detail at {}x{}, text may become illegible (needs about {:.0} kbps)",
+            self.bitrate_kbps,
+            frame.width,
+            frame.height,
+            required * pixels_per_second / 1000.0
+        );
+        tracing::warn!("{}", message);
+        Some(message)
+    }
+}
+
+impl FrameAnalyzer for SharpnessAnalyzer {
+    fn name(&self) -> &str {
+        "sharpness"
+    }
+
+    fn configure(&mut self, config: &AnalyticsConfig) {
+        self.analysis_width = config.analysis_width.max(1);
+    }
+
+    fn process_frame(&mut self, context: &FrameContext, output: &mut AnalyzerOutput) {
+        // Full resolution edge detail, on the same rows the sampler reads
+        let frame = context.frame;
+        let row_step = (frame.width / self.analysis_width).max(1) as usize;
+        let sharpness = laplacian_variance(frame, row_step);
+        output.series("sharpness", sharpness);
+        if let Some(message) = self.check_bitrate(frame, sharpness) {
+            output.warn(message);
+        }
+    }
+}
+
+/// Variance of the 4-neighbour Laplacian over every `row_step`th row of the full
+/// resolution frame. Text and UI edges score high, blurry or blocky frames score low.
+pub fn laplacian_variance(frame: &Frame, row_step: usize) -> f64 {
+    let width = frame.width as usize;
+    let height = frame.height as usize;
+    let stride = width * 3;
+    if width < 3 || height < 3 || frame.data.len() < stride * height {
+        return 0.0;
+    }
+
+    let luma_at = |x: usize, y: usize| {
+        let i = y * stride + x * 3;
+        luminance(frame.data[i], frame.data[i + 1], frame.data[i + 2]) as f64
+    };
+
+    let rows: Vec<usize> = (1..height - 1).step_by(row_step.max(1)).collect();
+    let (sum, sum_sq, count) = rows
+        .par_iter()
+        .map(|&y| {
+            let (mut sum, mut sum_sq) = (0.0, 0.0);
+            for x in 1..width - 1 {
+                let laplacian =
+                    luma_at(x - 1, y) + luma_at(x + 1, y) + luma_at(x, y - 1) + luma_at(x, y + 1)
+                        - 4.0 * luma_at(x, y);
+                sum += laplacian;
+                sum_sq += laplacian * laplacian;
+            }
+            (sum, sum_sq, (width - 2) as f64)
+        })
+        .reduce(|| (0.0, 0.0, 0.0), |a, b| (a.0 + b.0, a.1 + b.1, a.2 + b.2));
+
+    if count == 0.0 {
+        return 0.0;
+    }
+    let mean = sum / count;
+    sum_sq / count - mean * mean
+}
+
+/// Rough bits per pixel per frame an H.264 encode needs to keep detail of the given
+/// sharpness legible. Flat content gets away with very little, dense text needs more.
+pub fn required_bits_per_pixel(sharpness: f64) -> f64 {
+    (0.03 + sharpness / 25_000.0).min(0.25)
+}
@@ -0,0 +1,209 @@
+use crate::encryption::{InputFile, OutputFile, RecordingKey, SeekPoint};
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Entries between two points of the time index
+const INDEX_INTERVAL: u64 = 512;
+
+/// Append-only JSON-lines file holding every timeline entry of a recording, encrypted
+/// when the recording is
+pub struct TimelineStore {
+    path: PathBuf,
+    writer: OutputFile,
+    key: Option<Arc<RecordingKey>>,
+    len: u64,
+    /// Time of every `INDEX_INTERVAL`th entry and where it starts, so range reads skip
+    /// straight to it
+    index: Vec<(f64, SeekPoint)>,
+}
+
+impl TimelineStore {
+    pub fn create(path: PathBuf, key: Option<Arc<RecordingKey>>) -> Result<Self> {
+        let writer = OutputFile::create(&path, key.as_deref())
+            .with_context(|| format!("Failed to create timeline store {:?}", path))?;
+        Ok(Self {
+            path,
+            writer,
+            key,
+            len: 0,
+            index: Vec::new(),
+        })
+    }
+
+    pub fn append(&mut self, entry: &Value) -> Result<()> {
+        if self.len.is_multiple_of(INDEX_INTERVAL) {
+            let time = entry["time"].as_f64().unwrap_or(0.0);
+            self.index.push((time, self.writer.seek_point()?));
+        }
+        serde_json::to_writer(&mut self.writer, entry)?;
+        self.writer.write_all(b"\n")?;
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Flush and iterate over the raw JSON lines written so far
+    pub fn lines(&mut self) -> Result<impl Iterator<Item = std::io::Result<String>>> {
+        self.flush()?;
+        Ok(BufReader::new(InputFile::open_partial(&self.path, self.key.as_deref())?).lines())
+    }
+
+    /// Entries with `from <= time <= to`, see [`StoreReader::for_each_in`]
+    pub fn range(&mut self, from: Option<f64>, to: Option<f64>) -> Result<Vec<Value>> {
+        let mut entries = Vec::new();
+        self.reader()?
+            .for_each_in(from, to, usize::MAX, |entry| entries.push(entry))?;
+        Ok(entries)
+    }
+
+    /// Flush and take a reader over everything written so far
+    pub fn reader(&mut self) -> Result<StoreReader> {
+        self.flush()?;
+        Ok(StoreReader {
+            path: self.path.clone(),
+            key: self.key.clone(),
+            index: self.index.clone(),
+        })
+    }
+}
+
+/// Where a [`TimelineStore`]'s entries are, so a range can be read without holding the
+/// store itself
+#[derive(Clone)]
+pub struct StoreReader {
+    path: PathBuf,
+    key: Option<Arc<RecordingKey>>,
+    index: Vec<(f64, SeekPoint)>,
+}
+
+impl StoreReader {
+    /// Call `each` for the entries with `from <= time <= to`, read from the last index
+    /// point before `from` up to the first entry past `to`. Stops after `limit` entries,
+    /// returns whether the range was read to its end.
+    pub fn for_each_in(
+        &self,
+        from: Option<f64>,
+        to: Option<f64>,
+        limit: usize,
+        mut each: impl FnMut(Value),
+    ) -> Result<bool> {
+        let start = from.and_then(|from| {
+            self.index
+                .iter()
+                .rev()
+                .find(|(time, _)| *time < from)
+                .map(|(_, point)| *point)
+        });
+        let key = self.key.as_deref();
+        let file = match start {
+            Some(point) => InputFile::open_partial_at(&self.path, key, point)?,
+            None => InputFile::open_partial(&self.path, key)?,
+        };
+
+        let mut count = 0;
+        for line in BufReader::new(file).lines() {
+            let entry: Value = serde_json::from_str(&line?)?;
+            let time = entry["time"].as_f64().unwrap_or(0.0);
+            if to.is_some_and(|to| time > to) {
+                break;
+            }
+            if from.is_none_or(|from| time >= from) {
+                if count == limit {
+                    return Ok(false);
+                }
+                each(entry);
+                count += 1;
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Write a meta.json sidecar: the `metadata` fields followed by `entries`, each one already
+/// serialized JSON, so a long recording's timeline never has to be held in memory at once
+pub fn write_sidecar(
+    meta_path: &Path,
+    metadata: &Value,
+    entries: impl Iterator<Item = Result<String>>,
+    key: Option<&RecordingKey>,
+) -> Result<()> {
+    let mut out = OutputFile::create(meta_path, key)?;
+    out.write_all(b"{\n")?;
+    if let Some(fields) = metadata.as_object() {
+        for (key, value) in fields {
+            writeln!(out, "  {}: {},", serde_json::to_string(key)?, value)?;
+        }
+    }
+
+    out.write_all(b"  \"entries\": [")?;
+    let mut first = true;
+    for entry in entries {
+        out.write_all(if first { b"\n    " } else { b",\n    " })?;
+        out.write_all(entry?.as_bytes())?;
+        first = false;
+    }
+    out.write_all(b"\n  ]\n}\n")?;
+    out.finish()
+}
+
+/// Bounded in-memory view over the whole recording. When it fills up every other point
+/// is dropped and the sampling stride doubles, so it always spans the full session.
+pub struct DownsampledTimeline<T> {
+    entries: Vec<T>,
+    capacity: usize,
+    stride: u64,
+    seen: u64,
+}
+
+impl<T> DownsampledTimeline<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Vec::with_capacity(capacity),
+            capacity: capacity.max(2),
+            stride: 1,
+            seen: 0,
+        }
+    }
+
+    pub fn push(&mut self, entry: T) {
+        let index = self.seen;
+        self.seen += 1;
+        if !index.is_multiple_of(self.stride) {
+            return;
+        }
+
+        self.entries.push(entry);
+        if self.entries.len() > self.capacity {
+            let mut keep = false;
+            self.entries.retain(|_| {
+                keep = !keep;
+                keep
+            });
+            self.stride *= 2;
+        }
+    }
+
+    /// Whether every pushed entry is still held, i.e. nothing was decimated yet
+    pub fn is_complete(&self) -> bool {
+        self.stride == 1
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.entries.iter()
+    }
+}
@@ -0,0 +1,253 @@
+use super::store::DownsampledTimeline;
+use crate::capture::Frame;
+use crate::encryption::{self, RecordingKey};
+use anyhow::Result;
+use image::{imageops, ImageFormat, Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::Path;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThumbnailConfig {
+    /// Seconds between the regular thumbnails, scene changes add their own
+    pub interval_seconds: f64,
+    /// Thumbnail width in pixels, the height follows the frame's aspect ratio
+    pub width: u32,
+    /// Upper bound on kept thumbnails, long recordings spread them out instead
+    pub max_thumbnails: usize,
+    pub sheet_columns: u32,
+}
+
+impl Default for ThumbnailConfig {
+    fn default() -> Self {
+        Self {
+            interval_seconds: 10.0,
+            width: 240,
+            max_thumbnails: 120,
+            sheet_columns: 6,
+        }
+    }
+}
+
+struct Thumbnail {
+    time: f64,
+    scene_change: bool,
+    image: RgbImage,
+}
+
+/// Keeps small downscaled copies of frames at scene changes and fixed intervals, written
+/// out as PNGs plus a contact sheet when the recording is finalized
+pub struct ThumbnailStrip {
+    config: ThumbnailConfig,
+    next_interval: f64,
+    interval: DownsampledTimeline<Thumbnail>,
+    scenes: DownsampledTimeline<Thumbnail>,
+}
+
+impl ThumbnailStrip {
+    pub fn new(config: ThumbnailConfig) -> Self {
+        let capacity = (config.max_thumbnails / 2).max(1);
+        Self {
+            config,
+            next_interval: 0.0,
+            interval: DownsampledTimeline::new(capacity),
+            scenes: DownsampledTimeline::new(capacity),
+        }
+    }
+
+    pub fn update(&mut self, frame: &Frame, time: f64, scene_change: bool) {
+        let due = time >= self.next_interval;
+        if !due && !scene_change {
+            return;
+        }
+        let Some(image) = downscale(frame, self.config.width) else {
+            return;
+        };
+
+        let thumbnail = Thumbnail {
+            time,
+            scene_change,
+            image,
+        };
+        if scene_change {
+            self.scenes.push(thumbnail);
+        } else {
+            self.interval.push(thumbnail);
+        }
+        if due {
+            self.next_interval = time + self.config.interval_seconds.max(0.1);
+        }
+    }
+
+    /// Write every thumbnail into `<video>.thumbs/` and the contact sheet next to the
+    /// video, returning their paths for the sidecar. Both are encrypted with `key` if set.
+    pub fn save(&self, video_path: &Path, key: Option<&RecordingKey>) -> Result<Value> {
+        let mut thumbnails: Vec<&Thumbnail> =
+            self.interval.iter().chain(self.scenes.iter()).collect();
+        if thumbnails.is_empty() {
+            return Ok(Value::Null);
+        }
+        thumbnails.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        let mut dir = video_path.to_path_buf();
+        dir.set_extension("thumbs");
+        std::fs::create_dir_all(&dir)?;
+
+        let mut items = Vec::with_capacity(thumbnails.len());
+        for thumbnail in &thumbnails {
+            let path = dir.join(format!("{:09}.png", (thumbnail.time * 1000.0) as u64));
+            save_png(&thumbnail.image, &path, key)?;
+            items.push(json!({
+                "time": thumbnail.time,
+                "sceneChange": thumbnail.scene_change,
+                "path": path.to_string_lossy(),
+            }));
+        }
+
+        let mut sheet_path = video_path.to_path_buf();
+        sheet_path.set_extension("contact.png");
+        save_png(&self.contact_sheet(&thumbnails), &sheet_path, key)?;
+
+        Ok(json!({
+            "dir": dir.to_string_lossy(),
+            "contact_sheet": sheet_path.to_string_lossy(),
+            "items": items,
+        }))
+    }
+
+    /// Grid of thumbnails in time order, each captioned with its timestamp
+    fn contact_sheet(&self, thumbnails: &[&Thumbnail]) -> RgbImage {
+        const GAP: u32 = 4;
+        const SCALE: u32 = 2;
+        let caption_height = GLYPH_HEIGHT * SCALE + 2 * GAP;
+
+        let cell_width = thumbnails
+            .iter()
+            .map(|t| t.image.width())
+            .max()
+            .unwrap_or(1);
+        let cell_height = thumbnails
+            .iter()
+            .map(|t| t.image.height())
+            .max()
+            .unwrap_or(1)
+            + caption_height;
+        let columns = self.config.sheet_columns.clamp(1, thumbnails.len() as u32);
+        let rows = (thumbnails.len() as u32).div_ceil(columns);
+
+        let mut sheet = RgbImage::from_pixel(
+            columns * (cell_width + GAP) + GAP,
+            rows * (cell_height + GAP) + GAP,
+            Rgb([24, 24, 24]),
+        );
+        for (i, thumbnail) in thumbnails.iter().enumerate() {
+            let x = GAP + (i as u32 % columns) * (cell_width + GAP);
+            let y = GAP + (i as u32 / columns) * (cell_height + GAP);
+            imageops::replace(&mut sheet, &thumbnail.image, x as i64, y as i64);
+
+            // Scene change thumbnails get an accent coloured caption
+            let color = if thumbnail.scene_change {
+                Rgb([255, 196, 64])
+            } else {
+                Rgb([230, 230, 230])
+            };
+            draw_text(
+                &mut sheet,
+                &format_timestamp(thumbnail.time),
+                x,
+                y + thumbnail.image.height() + GAP,
+                SCALE,
+                color,
+            );
+        }
+        sheet
+    }
+}
+
+fn save_png(image: &RgbImage, path: &Path, key: Option<&RecordingKey>) -> Result<()> {
+    let mut png = std::io::Cursor::new(Vec::new());
+    image.write_to(&mut png, ImageFormat::Png)?;
+    encryption::write_file(path, png.get_ref(), key)
+}
+
+/// Box-filtered copy of the frame `width` pixels wide
+pub fn downscale(frame: &Frame, width: u32) -> Option<RgbImage> {
+    let (src_width, src_height) = (frame.width as usize, frame.height as usize);
+    if src_width == 0 || src_height == 0 || frame.data.len() < src_width * src_height * 3 {
+        return None;
+    }
+    let width = width.clamp(1, frame.width);
+    let height = ((frame.height as u64 * width as u64 / frame.width as u64) as u32).max(1);
+
+    Some(RgbImage::from_fn(width, height, |x, y| {
+        let x0 = x as usize * src_width / width as usize;
+        let x1 = ((x as usize + 1) * src_width / width as usize).max(x0 + 1);
+        let y0 = y as usize * src_height / height as usize;
+        let y1 = ((y as usize + 1) * src_height / height as usize).max(y0 + 1);
+
+        let mut sum = [0u32; 3];
+        for sy in y0..y1 {
+            let row = &frame.data[(sy * src_width + x0) * 3..(sy * src_width + x1) * 3];
+            for pixel in row.chunks_exact(3) {
+                sum[0] += pixel[0] as u32;
+                sum[1] += pixel[1] as u32;
+                sum[2] += pixel[2] as u32;
+            }
+        }
+        let count = ((x1 - x0) * (y1 - y0)) as u32;
+        Rgb(sum.map(|s| (s / count) as u8))
+    }))
+}
+
+fn format_timestamp(seconds: f64) -> String {
+    let total = seconds.max(0.0) as u64;
+    if total >= 3600 {
+        format!("{}:{:02}:{:02}", total / 3600, total / 60 % 60, total % 60)
+    } else {
+        format!("{:02}:{:02}", total / 60, total % 60)
+    }
+}
+
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+
+/// 3x5 bitmap glyphs for the characters timestamps use, one row per entry
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        _ => [0; 5],
+    }
+}
+
+fn draw_text(image: &mut RgbImage, text: &str, x: u32, y: u32, scale: u32, color: Rgb<u8>) {
+    for (i, c) in text.chars().enumerate() {
+        let origin_x = x + i as u32 * (GLYPH_WIDTH + 1) * scale;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - col)) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let px = origin_x + col * scale + dx;
+                        let py = y + row as u32 * scale + dy;
+                        if px < image.width() && py < image.height() {
+                            image.put_pixel(px, py, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
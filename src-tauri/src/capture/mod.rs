@@ -9,6 +9,16 @@ pub enum CaptureSource {
     Window(String),
 }
 
+impl CaptureSource {
+    /// `monitor:<id>` or `window:<id>`, as stored in the sidecar and the library
+    pub fn label(&self) -> String {
+        match self {
+            CaptureSource::Monitor(id) => format!("monitor:{}", id),
+            CaptureSource::Window(id) => format!("window:{}", id),
+        }
+    }
+}
+
 #[async_trait::async_trait]
 pub trait CaptureTrait: Send + Sync {
     async fn initialize(&mut self) -> Result<()>;
@@ -0,0 +1,225 @@
+use crate::analytics::chapters::{self, Chapter};
+use crate::encryption::{self, RecordingKey};
+use crate::integrity;
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+/// Chapters stored in a recording's sidecar
+pub fn load(video_path: &Path) -> Result<Vec<Chapter>> {
+    let metadata = read_metadata(video_path)?;
+    Ok(serde_json::from_value(metadata["chapters"].clone()).unwrap_or_default())
+}
+
+/// Split the chapter containing `start` at that point, the new chapter runs to where the
+/// old one ended
+pub async fn add_chapter(video_path: &Path, start: f64, title: String) -> Result<Vec<Chapter>> {
+    let mut chapters = load(video_path)?;
+    if !chapters::split(&mut chapters, start, title) {
+        return Err(anyhow::anyhow!("No chapter contains {:.1}s", start));
+    }
+
+    save(video_path, &chapters).await?;
+    Ok(chapters)
+}
+
+pub async fn rename_chapter(
+    video_path: &Path,
+    index: usize,
+    title: String,
+) -> Result<Vec<Chapter>> {
+    let mut chapters = load(video_path)?;
+    let chapter = chapters
+        .get_mut(index)
+        .ok_or_else(|| anyhow::anyhow!("No chapter {}", index))?;
+    chapter.title = title;
+    chapter.auto = false;
+
+    save(video_path, &chapters).await?;
+    Ok(chapters)
+}
+
+/// Store edited chapters in the sidecar and rewrite the chapter tracks. A sealed
+/// recording is checked before and resealed after.
+async fn save(video_path: &Path, chapters: &[Chapter]) -> Result<()> {
+    let meta_path = meta_path(video_path);
+    let mut rewritten = vec![meta_path.clone()];
+    if embeds_chapters(video_path, None) {
+        rewritten.push(video_path.to_path_buf());
+    }
+    integrity::check_unchanged(video_path, &rewritten).await?;
+
+    let mut metadata = read_metadata(video_path)?;
+    metadata["chapters"] = serde_json::to_value(chapters)?;
+    std::fs::write(&meta_path, serde_json::to_vec_pretty(&metadata)?)?;
+    let mut written = write_tracks(video_path, chapters, None).await?;
+    written.push(meta_path);
+    integrity::update(video_path, &written).await
+}
+
+/// Write the `.chapters.vtt` track next to the recording and, for Matroska recordings the
+/// app produced itself, embed the chapters in the container. Requires `ffmpeg` and
+/// `ffprobe` binaries on the PATH for the latter. Encrypted recordings (`key` set) and
+/// files the app only analyzed get the track alone, encrypted too when keyed. Returns
+/// the files written.
+pub async fn write_tracks(
+    video_path: &Path,
+    chapters: &[Chapter],
+    key: Option<&RecordingKey>,
+) -> Result<Vec<PathBuf>> {
+    let mut vtt_path = video_path.to_path_buf();
+    vtt_path.set_extension("chapters.vtt");
+    encryption::write_file(&vtt_path, chapters::to_webvtt(chapters).as_bytes(), key)
+        .with_context(|| format!("Failed to write {:?}", vtt_path))?;
+    let mut written = vec![vtt_path];
+
+    if embeds_chapters(video_path, key) {
+        embed_matroska(video_path, chapters).await?;
+        written.push(video_path.to_path_buf());
+    }
+
+    tracing::info!("Wrote {} chapters for {:?}", chapters.len(), video_path);
+    Ok(written)
+}
+
+/// Whether `write_tracks` also rewrites the video itself
+fn embeds_chapters(video_path: &Path, key: Option<&RecordingKey>) -> bool {
+    let is_matroska = video_path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("mkv"));
+    is_matroska && key.is_none() && app_owned(video_path)
+}
+
+/// Only recordings with a segment manifest were written by the encoder, anything else is
+/// the user's own file and is never rewritten
+fn app_owned(video_path: &Path) -> bool {
+    let mut manifest_path = video_path.to_path_buf();
+    manifest_path.set_extension("manifest.json");
+    manifest_path.exists() && std::fs::metadata(video_path).is_ok_and(|m| m.len() > 0)
+}
+
+/// Remux the file with the chapters as its Matroska chapter list, streams are copied.
+/// The original is only replaced once the remux probes with the same streams and duration.
+async fn embed_matroska(video_path: &Path, chapters: &[Chapter]) -> Result<()> {
+    let mut ffmetadata_path = video_path.to_path_buf();
+    ffmetadata_path.set_extension("ffmetadata");
+    std::fs::write(&ffmetadata_path, chapters::to_ffmetadata(chapters))?;
+
+    let mut remuxed_path = video_path.to_path_buf();
+    remuxed_path.set_extension("chapters.mkv");
+    let status = Command::new("ffmpeg")
+        .args(["-y", "-v", "error", "-i"])
+        .arg(video_path)
+        .arg("-i")
+        .arg(&ffmetadata_path)
+        .args([
+            "-map",
+            "0",
+            "-map_metadata",
+            "0",
+            "-map_chapters",
+            "1",
+            "-c",
+            "copy",
+        ])
+        .arg(&remuxed_path)
+        .status()
+        .await;
+    let _ = std::fs::remove_file(&ffmetadata_path);
+
+    let status = status.context("Failed to run ffmpeg")?;
+    if !status.success() {
+        let _ = std::fs::remove_file(&remuxed_path);
+        return Err(anyhow::anyhow!("ffmpeg exited with {}", status));
+    }
+
+    if let Err(e) = verify_remux(video_path, &remuxed_path, chapters.len()).await {
+        let _ = std::fs::remove_file(&remuxed_path);
+        return Err(e.context(format!("Kept {:?} as it was", video_path)));
+    }
+    std::fs::rename(&remuxed_path, video_path)?;
+    Ok(())
+}
+
+/// Check the remux kept every stream and the full duration, and carries the chapters
+async fn verify_remux(original: &Path, remuxed: &Path, chapter_count: usize) -> Result<()> {
+    let (streams, duration, _) = probe_container(original).await?;
+    let (remuxed_streams, remuxed_duration, remuxed_chapters) = probe_container(remuxed).await?;
+
+    if remuxed_streams != streams {
+        return Err(anyhow::anyhow!(
+            "Remux has {} streams, the recording has {}",
+            remuxed_streams,
+            streams
+        ));
+    }
+    // Container durations are rounded differently by muxers, allow a frame or two
+    if (remuxed_duration - duration).abs() > 0.1_f64.max(duration * 0.001) {
+        return Err(anyhow::anyhow!(
+            "Remux is {:.2}s long, the recording {:.2}s",
+            remuxed_duration,
+            duration
+        ));
+    }
+    if remuxed_chapters != chapter_count {
+        return Err(anyhow::anyhow!(
+            "Remux has {} chapters, expected {}",
+            remuxed_chapters,
+            chapter_count
+        ));
+    }
+    Ok(())
+}
+
+/// Stream count, duration in seconds and chapter count of a container
+async fn probe_container(path: &Path) -> Result<(usize, f64, usize)> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-show_entries",
+            "stream=index:format=duration",
+            "-show_chapters",
+            "-of",
+            "json",
+        ])
+        .arg(path)
+        .output()
+        .await
+        .context("Failed to run ffprobe")?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "ffprobe failed on {:?}: {}",
+            path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let probe: Value = serde_json::from_slice(&output.stdout)?;
+    let count = |key: &str| probe[key].as_array().map(Vec::len).unwrap_or(0);
+    let duration = probe["format"]["duration"]
+        .as_str()
+        .and_then(|d| d.parse::<f64>().ok())
+        .ok_or_else(|| anyhow::anyhow!("No duration for {:?}", path))?;
+    Ok((count("streams"), duration, count("chapters")))
+}
+
+fn meta_path(video_path: &Path) -> PathBuf {
+    let mut meta_path = video_path.to_path_buf();
+    meta_path.set_extension("meta.json");
+    meta_path
+}
+
+fn read_metadata(video_path: &Path) -> Result<Value> {
+    let meta_path = meta_path(video_path);
+    if encryption::is_encrypted(&meta_path) {
+        return Err(anyhow::anyhow!(
+            "Recording is encrypted, decrypt it first"
+        ));
+    }
+    Ok(serde_json::from_str(
+        &std::fs::read_to_string(&meta_path)
+            .with_context(|| format!("Failed to read metadata {:?}", meta_path))?,
+    )?)
+}
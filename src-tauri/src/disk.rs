@@ -0,0 +1,153 @@
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use sysinfo::Disks;
+
+/// Space left free on the volume no matter what, so the recording can still be finalized
+/// and the rest of the system keeps working
+const RESERVE_BYTES: u64 = 256 * 1024 * 1024;
+/// Warn once less than this much recording time fits on the volume
+const LOW_SPACE_SECONDS: f64 = 600.0;
+/// Stop and finalize once less than this much recording time fits on the volume
+const CRITICAL_SPACE_SECONDS: f64 = 30.0;
+/// Recording time that has to fit before a recording is allowed to start
+const MIN_START_SECONDS: f64 = 60.0;
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, PartialOrd)]
+#[serde(rename_all = "snake_case")]
+pub enum DiskLevel {
+    Ok,
+    Low,
+    Critical,
+}
+
+/// Watches free space on the volume a recording is written to, and how fast the encoder
+/// is estimated to fill it. The encoder only knows its sizes from the bitrate preset, so
+/// write rates and the recording time left are estimates.
+pub struct DiskGuard {
+    dir: PathBuf,
+    disks: Disks,
+    /// Bitrate estimate used until the encoder has reported sizes twice
+    estimated_bytes_per_second: f64,
+    last_sample: Option<(Instant, u64)>,
+    estimated_throughput: Option<f64>,
+    free_bytes: Option<u64>,
+    level: DiskLevel,
+}
+
+impl DiskGuard {
+    pub fn new(dir: &Path, estimated_bytes_per_second: u64) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            disks: Disks::new_with_refreshed_list(),
+            estimated_bytes_per_second: estimated_bytes_per_second as f64,
+            last_sample: None,
+            estimated_throughput: None,
+            free_bytes: None,
+            level: DiskLevel::Ok,
+        }
+    }
+
+    /// Fail if the volume cannot hold at least a minute of recording at the estimated rate
+    pub fn check_before_start(&mut self) -> Result<()> {
+        self.disks.refresh();
+        let Some(free) = available_space(&self.disks, &self.dir) else {
+            tracing::warn!("Could not determine free space for {:?}", self.dir);
+            return Ok(());
+        };
+        let needed = RESERVE_BYTES + (self.estimated_bytes_per_second * MIN_START_SECONDS) as u64;
+        if free < needed {
+            return Err(anyhow::anyhow!(
+                "Not enough disk space in {:?}: {} MB free, at least {} MB needed",
+                self.dir,
+                free / 1024 / 1024,
+                needed / 1024 / 1024
+            ));
+        }
+        self.free_bytes = Some(free);
+        Ok(())
+    }
+
+    /// Sample free space and the encoder's `estimated_bytes_written`. Returns a warning
+    /// payload when the level got worse since the last check.
+    pub fn check(&mut self, estimated_bytes_written: u64) -> Option<Value> {
+        let now = Instant::now();
+        if let Some((time, bytes)) = self.last_sample {
+            let elapsed = now.duration_since(time).as_secs_f64();
+            if elapsed > 0.0 {
+                let written = estimated_bytes_written.saturating_sub(bytes);
+                self.estimated_throughput = Some(written as f64 / elapsed);
+            }
+        }
+        self.last_sample = Some((now, estimated_bytes_written));
+
+        self.disks.refresh();
+        let free = available_space(&self.disks, &self.dir)?;
+        self.free_bytes = Some(free);
+
+        let seconds_left = self.seconds_left(free);
+        let level = if free <= RESERVE_BYTES || seconds_left < CRITICAL_SPACE_SECONDS {
+            DiskLevel::Critical
+        } else if seconds_left < LOW_SPACE_SECONDS {
+            DiskLevel::Low
+        } else {
+            DiskLevel::Ok
+        };
+        let worse = level > self.level;
+        self.level = level;
+        if !worse {
+            return None;
+        }
+
+        tracing::warn!(
+            "Disk space {:?} for {:?}: {} MB free, an estimated {:.0}s of recording left",
+            level,
+            self.dir,
+            free / 1024 / 1024,
+            seconds_left
+        );
+        Some(json!({
+            "level": level,
+            "path": self.dir,
+            "free_bytes": free,
+            "estimated_seconds_left": seconds_left,
+        }))
+    }
+
+    /// Bytes per second the encoder estimated it wrote between the last two checks
+    pub fn estimated_throughput(&self) -> f64 {
+        self.estimated_throughput.unwrap_or(0.0)
+    }
+
+    pub fn free_bytes(&self) -> Option<u64> {
+        self.free_bytes
+    }
+
+    pub fn level(&self) -> DiskLevel {
+        self.level
+    }
+
+    /// Recording time that still fits above the reserve at the estimated write rate
+    fn seconds_left(&self, free: u64) -> f64 {
+        let rate = self
+            .estimated_throughput
+            .filter(|t| *t > 0.0)
+            .unwrap_or(self.estimated_bytes_per_second);
+        if rate <= 0.0 {
+            return f64::INFINITY;
+        }
+        free.saturating_sub(RESERVE_BYTES) as f64 / rate
+    }
+}
+
+/// Free space on the disk mounted closest to `path`
+fn available_space(disks: &Disks, path: &Path) -> Option<u64> {
+    disks
+        .list()
+        .iter()
+        .filter(|disk| path.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().components().count())
+        .map(|disk| disk.available_space())
+}
@@ -1,12 +1,80 @@
 use crate::capture::Frame;
-use anyhow::Result;
-use serde_json::json;
-use std::path::PathBuf;
+use crate::encryption::{OutputFile, RecordingKey};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Instant;
 use std::collections::VecDeque;
 
+/// Target video bitrate, chosen by the user when a recording starts
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BitratePreset {
+    Low,
+    #[default]
+    Medium,
+    High,
+    Ultra,
+}
+
+impl BitratePreset {
+    pub fn kbps(self) -> u32 {
+        match self {
+            BitratePreset::Low => 1_500,
+            BitratePreset::Medium => 4_000,
+            BitratePreset::High => 8_000,
+            BitratePreset::Ultra => 16_000,
+        }
+    }
+
+    /// Expected write rate at this bitrate
+    pub fn bytes_per_second(self) -> u64 {
+        self.kbps() as u64 * 1000 / 8
+    }
+}
+
+/// When to roll over to a new output file. With neither limit set the recording is a
+/// single file at the session's output path. The size limit applies to the size estimated
+/// from the bitrate preset, not the muxed file.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SegmentConfig {
+    pub max_duration_seconds: Option<f64>,
+    pub max_size_mb: Option<u64>,
+}
+
+impl SegmentConfig {
+    fn enabled(&self) -> bool {
+        self.max_duration_seconds.is_some() || self.max_size_mb.is_some()
+    }
+}
+
+/// One output file of a recording, `start` is its offset in session seconds
+#[derive(Clone, Debug, Serialize)]
+pub struct Segment {
+    pub index: u32,
+    pub path: PathBuf,
+    pub start: f64,
+    pub duration: f64,
+    pub frames: u64,
+    /// Frames times the preset's per-frame size, the muxed file may differ
+    pub estimated_bytes: u64,
+}
+
 pub struct Encoder {
     output_path: PathBuf,
+    bitrate_preset: BitratePreset,
+    segment_config: SegmentConfig,
+    /// Segments are written through an encrypting stream when set
+    encryption: Option<Arc<RecordingKey>>,
+    /// Finalized segments, in order
+    segments: Vec<Segment>,
+    /// Segment frames are currently written to, opened on the first frame
+    current_segment: Option<Segment>,
+    /// Where the current segment's packets go, encrypted when there is a key
+    current_output: Option<OutputFile>,
     width: u32,
     height: u32,
     frame_count: u64,
@@ -55,9 +123,20 @@ impl FpsCalculator {
 }
 
 impl Encoder {
-    pub async fn new(output_path: PathBuf) -> Result<Self> {
+    pub async fn new(
+        output_path: PathBuf,
+        bitrate_preset: BitratePreset,
+        segment_config: SegmentConfig,
+        encryption: Option<Arc<RecordingKey>>,
+    ) -> Result<Self> {
         Ok(Self {
             output_path,
+            bitrate_preset,
+            segment_config,
+            encryption,
+            segments: Vec::new(),
+            current_segment: None,
+            current_output: None,
             width: 0,
             height: 0,
             frame_count: 0,
@@ -81,13 +160,30 @@ impl Encoder {
         // 5. Open output file
         
         // For now, we'll use a file-based approach that can be extended
-        tracing::info!("Encoder initialized for output: {:?}", self.output_path);
+        tracing::info!(
+            "Encoder initialized for output: {:?} at {} kbps",
+            self.output_path,
+            self.bitrate_preset.kbps()
+        );
         Ok(())
     }
 
-    pub async fn encode_frame(&mut self, frame: &Frame) -> Result<()> {
+    /// Encode a frame captured at `time` seconds into the session (pauses excluded)
+    pub async fn encode_frame(&mut self, frame: &Frame, time: f64) -> Result<()> {
         let start = Instant::now();
 
+        // Roll over before writing the frame that would exceed the segment limits
+        if self
+            .current_segment
+            .as_ref()
+            .is_some_and(|segment| self.segment_full(segment, time))
+        {
+            self.finish_segment().await?;
+        }
+        if self.current_segment.is_none() {
+            self.open_segment(time)?;
+        }
+
         // Set dimensions on first frame
         if self.width == 0 {
             self.width = frame.width;
@@ -112,12 +208,18 @@ impl Encoder {
         // 1. Convert RGB frame to YUV420p format
         // 2. Create AVFrame from frame data
         // 3. Encode frame using codec context
-        // 4. Write encoded packet to output file
+        // 4. Write encoded packet to `current_output`
         
         // For now, we simulate encoding by just tracking metrics
         // In a real implementation, you would write the frame data to FFmpeg
         
         self.frame_count += 1;
+        let bytes_per_frame = self.bytes_per_frame();
+        if let Some(segment) = self.current_segment.as_mut() {
+            segment.frames += 1;
+            segment.estimated_bytes += bytes_per_frame;
+            segment.duration = time - segment.start;
+        }
         self.frame_timestamps.push_back(start);
         self.encode_time += start.elapsed();
         
@@ -129,7 +231,110 @@ impl Encoder {
         Ok(())
     }
 
+    /// Keep showing the previous frame up to `time` instead of encoding an identical one,
+    /// so skipped idle frames don't shift the output off the session clock
+    pub fn hold_frame(&mut self, time: f64) {
+        if let Some(segment) = self.current_segment.as_mut() {
+            segment.duration = time - segment.start;
+        }
+        // Skipped on purpose, the gap must not count as dropped frames
+        self.frame_timestamps.clear();
+    }
+
+    fn segment_full(&self, segment: &Segment, time: f64) -> bool {
+        let too_long = self
+            .segment_config
+            .max_duration_seconds
+            .is_some_and(|max| time - segment.start >= max);
+        let too_big = self.segment_config.max_size_mb.is_some_and(|max| {
+            segment.estimated_bytes + self.bytes_per_frame() > max * 1024 * 1024
+        });
+        too_long || too_big
+    }
+
+    /// Estimated size of an encoded frame at the preset bitrate and 30 FPS target
+    fn bytes_per_frame(&self) -> u64 {
+        self.bitrate_preset.bytes_per_second() / 30
+    }
+
+    fn open_segment(&mut self, time: f64) -> Result<()> {
+        let index = self.segments.len() as u32 + 1;
+        let path = if self.segment_config.enabled() {
+            segment_path(&self.output_path, index)
+        } else {
+            self.output_path.clone()
+        };
+
+        // Packets only reach the disk through this, so an encrypted recording never has
+        // plaintext in its segments. In production implementation: open a new output
+        // context with the same stream parameters, starting on a keyframe, with a custom
+        // IO context writing here.
+        self.current_output = Some(OutputFile::create(&path, self.encryption.as_deref())?);
+        tracing::info!("Opened segment {} at {:.1}s: {:?}", index, time, path);
+        self.current_segment = Some(Segment {
+            index,
+            path,
+            start: time,
+            duration: 0.0,
+            frames: 0,
+            estimated_bytes: 0,
+        });
+        Ok(())
+    }
+
+    async fn finish_segment(&mut self) -> Result<()> {
+        let Some(segment) = self.current_segment.take() else {
+            return Ok(());
+        };
+
+        // In production implementation: flush the encoder and write the trailer so every
+        // segment is playable on its own
+        if let Some(output) = self.current_output.take() {
+            output
+                .finish()
+                .with_context(|| format!("Failed to finish segment {:?}", segment.path))?;
+        }
+        tracing::info!(
+            "Finalized segment {}: {} frames, {:.1}s",
+            segment.index,
+            segment.frames,
+            segment.duration
+        );
+        self.segments.push(segment);
+        Ok(())
+    }
+
+    /// Estimated total size of all segments so far
+    pub fn estimated_bytes_written(&self) -> u64 {
+        self.segments
+            .iter()
+            .chain(self.current_segment.as_ref())
+            .map(|s| s.estimated_bytes)
+            .sum()
+    }
+
+    /// Segments written so far and where the manifest listing them is saved
+    pub fn manifest(&self) -> Value {
+        let mut manifest_path = self.output_path.clone();
+        manifest_path.set_extension("manifest.json");
+        let segments: Vec<&Segment> = self
+            .segments
+            .iter()
+            .chain(self.current_segment.as_ref())
+            .collect();
+        json!({
+            "video_path": self.output_path,
+            "manifest_path": manifest_path,
+            "segmented": self.segment_config.enabled(),
+            "encrypted": self.encryption.is_some(),
+            "duration": segments.last().map(|s| s.start + s.duration).unwrap_or(0.0),
+            "segments": segments,
+        })
+    }
+
     pub async fn finalize(&mut self) -> Result<()> {
+        self.finish_segment().await?;
+
         // Finalize FFmpeg encoder
         // In production implementation:
         // 1. Flush encoder buffers (encode remaining frames)
@@ -142,7 +347,11 @@ impl Encoder {
             self.frame_count,
             self.dropped_frames
         );
-        
+
+        let manifest = self.manifest();
+        if let Some(path) = manifest["manifest_path"].as_str() {
+            std::fs::write(path, serde_json::to_vec_pretty(&manifest)?)?;
+        }
         Ok(())
     }
 
@@ -176,6 +385,8 @@ impl Encoder {
             "encode_fps": (encode_fps * 10.0).round() / 10.0,
             "dropped_frames": self.dropped_frames,
             "encode_latency": (avg_encode_time * 10.0).round() / 10.0,
+            "bitrate_kbps": self.bitrate_preset.kbps(),
+            "segment": self.current_segment.as_ref().map(|s| s.index),
             // CPU and memory will be added by session manager
             "cpu_usage": 0.0,
             "memory_usage": 0,
@@ -183,3 +394,16 @@ impl Encoder {
     }
 }
 
+
+/// `recording_x.mkv` becomes `recording_x_part001.mkv`, `recording_x_part002.mkv`, ...
+fn segment_path(output_path: &Path, index: u32) -> PathBuf {
+    let stem = output_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("recording");
+    let extension = output_path
+        .extension()
+        .and_then(|s| s.to_str())
+        .unwrap_or("mkv");
+    output_path.with_file_name(format!("{}_part{:03}.{}", stem, index, extension))
+}
@@ -0,0 +1,780 @@
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// First bytes of every encrypted file
+const MAGIC: &[u8; 8] = b"SRENC01\n";
+/// Magic, key salt, key kind and the file's nonce prefix
+const HEADER_LEN: usize = 32;
+/// Plaintext bytes per chunk, a flush seals a shorter one
+const CHUNK_SIZE: usize = 64 * 1024;
+const TAG_LEN: usize = 16;
+
+const KDF_PASSPHRASE: u8 = 0;
+const KDF_KEYFILE: u8 = 1;
+
+/// Where a recording's key comes from. Passphrases go through Argon2id; keyfiles must hold
+/// at least 32 bytes of random data.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum KeySource {
+    Passphrase { passphrase: String },
+    Keyfile { path: PathBuf },
+}
+
+impl std::fmt::Debug for KeySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeySource::Passphrase { .. } => write!(f, "Passphrase"),
+            KeySource::Keyfile { path } => write!(f, "Keyfile({:?})", path),
+        }
+    }
+}
+
+impl KeySource {
+    fn kdf(&self) -> u8 {
+        match self {
+            KeySource::Passphrase { .. } => KDF_PASSPHRASE,
+            KeySource::Keyfile { .. } => KDF_KEYFILE,
+        }
+    }
+}
+
+/// AES-256-GCM key shared by every file of one recording. The salt is stored in each
+/// file's header, so the key can be derived again from the same passphrase or keyfile.
+pub struct RecordingKey {
+    cipher: Aes256Gcm,
+    salt: [u8; 16],
+    kdf: u8,
+}
+
+impl RecordingKey {
+    /// A fresh key for a new recording
+    pub fn new(source: &KeySource) -> Result<Self> {
+        let mut salt = [0u8; 16];
+        random(&mut salt)?;
+        Self::derive(source, salt)
+    }
+
+    fn derive(source: &KeySource, salt: [u8; 16]) -> Result<Self> {
+        let mut key = [0u8; 32];
+        match source {
+            KeySource::Passphrase { passphrase } => {
+                if passphrase.is_empty() {
+                    return Err(anyhow::anyhow!("Passphrase must not be empty"));
+                }
+                argon2::Argon2::default()
+                    .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+                    .map_err(|e| anyhow::anyhow!("Failed to derive key: {}", e))?;
+            }
+            KeySource::Keyfile { path } => {
+                let contents = std::fs::read(path)
+                    .with_context(|| format!("Failed to read keyfile {:?}", path))?;
+                if contents.len() < 32 {
+                    return Err(anyhow::anyhow!(
+                        "Keyfile {:?} must hold at least 32 bytes",
+                        path
+                    ));
+                }
+                let mut hasher = Sha256::new();
+                hasher.update(b"screen-recorder keyfile");
+                hasher.update(salt);
+                hasher.update(&contents);
+                key.copy_from_slice(&hasher.finalize());
+            }
+        }
+        Ok(Self {
+            cipher: Aes256Gcm::new(&key.into()),
+            salt,
+            kdf: source.kdf(),
+        })
+    }
+
+    /// The key a file was encrypted with, derived from `source`
+    fn unlock(source: &KeySource, header: &Header) -> Result<Self> {
+        if source.kdf() != header.kdf {
+            return Err(anyhow::anyhow!(match header.kdf {
+                KDF_PASSPHRASE => "Recording is encrypted with a passphrase, not a keyfile",
+                _ => "Recording is encrypted with a keyfile, not a passphrase",
+            }));
+        }
+        Self::derive(source, header.salt)
+    }
+}
+
+fn random(buffer: &mut [u8]) -> Result<()> {
+    getrandom::getrandom(buffer).map_err(|e| anyhow::anyhow!("No randomness available: {}", e))
+}
+
+struct Header {
+    bytes: [u8; HEADER_LEN],
+    salt: [u8; 16],
+    kdf: u8,
+}
+
+impl Header {
+    fn new(key: &RecordingKey) -> Result<Self> {
+        let mut bytes = [0u8; HEADER_LEN];
+        bytes[..8].copy_from_slice(MAGIC);
+        bytes[8..24].copy_from_slice(&key.salt);
+        bytes[24] = key.kdf;
+        random(&mut bytes[25..])?;
+        Ok(Self::parse(bytes))
+    }
+
+    fn parse(bytes: [u8; HEADER_LEN]) -> Self {
+        let mut salt = [0u8; 16];
+        salt.copy_from_slice(&bytes[8..24]);
+        Self {
+            bytes,
+            salt,
+            kdf: bytes[24],
+        }
+    }
+
+    fn read(reader: &mut impl Read) -> Result<Self> {
+        let mut bytes = [0u8; HEADER_LEN];
+        reader.read_exact(&mut bytes)?;
+        if &bytes[..8] != MAGIC {
+            return Err(anyhow::anyhow!("Not an encrypted file"));
+        }
+        Ok(Self::parse(bytes))
+    }
+
+    /// Nonce prefix, chunk counter and whether it is the last chunk, as in the STREAM
+    /// construction, so chunks can't be reordered, dropped or cut off unnoticed
+    fn nonce(&self, counter: u32, last: bool) -> [u8; 12] {
+        let mut nonce = [0u8; 12];
+        nonce[..7].copy_from_slice(&self.bytes[25..]);
+        nonce[7..11].copy_from_slice(&counter.to_be_bytes());
+        nonce[11] = last as u8;
+        nonce
+    }
+}
+
+/// Whether `path` was written encrypted
+pub fn is_encrypted(path: &Path) -> bool {
+    let mut magic = [0u8; 8];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok_and(|_| &magic == MAGIC)
+}
+
+/// Encrypts everything written to it as a header followed by length-prefixed chunks.
+/// `flush` seals the buffered bytes as a chunk of their own, so a stream that is
+/// flushed regularly can be read back up to the last flush after a crash.
+pub struct EncryptedWriter<W: Write> {
+    inner: W,
+    cipher: Aes256Gcm,
+    header: Header,
+    buffer: Vec<u8>,
+    counter: u32,
+    /// Bytes handed to `inner` so far
+    offset: u64,
+}
+
+impl<W: Write> EncryptedWriter<W> {
+    pub fn new(mut inner: W, key: &RecordingKey) -> Result<Self> {
+        let header = Header::new(key)?;
+        inner.write_all(&header.bytes)?;
+        Ok(Self {
+            inner,
+            cipher: key.cipher.clone(),
+            header,
+            buffer: Vec::with_capacity(CHUNK_SIZE),
+            counter: 0,
+            offset: HEADER_LEN as u64,
+        })
+    }
+
+    fn seal(&mut self, last: bool) -> std::io::Result<()> {
+        let nonce = self.header.nonce(self.counter, last);
+        let payload = Payload {
+            msg: &self.buffer,
+            aad: &self.header.bytes,
+        };
+        let chunk = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), payload)
+            .map_err(|_| std::io::Error::other("Encryption failed"))?;
+        self.inner.write_all(&(chunk.len() as u32).to_le_bytes())?;
+        self.inner.write_all(&chunk)?;
+        self.offset += 4 + chunk.len() as u64;
+        self.buffer.clear();
+        self.counter = self
+            .counter
+            .checked_add(1)
+            .ok_or_else(|| std::io::Error::other("Encrypted stream too long"))?;
+        Ok(())
+    }
+
+    /// Seal the last chunk, without it the stream reads as truncated
+    pub fn finish(mut self) -> Result<W> {
+        self.seal(true)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for EncryptedWriter<W> {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        let len = data.len().min(CHUNK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&data[..len]);
+        if self.buffer.len() == CHUNK_SIZE {
+            self.seal(false)?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if !self.buffer.is_empty() {
+            self.seal(false)?;
+        }
+        self.inner.flush()
+    }
+}
+
+/// Reads back what an [`EncryptedWriter`] wrote, failing on any tampering. Unless
+/// `partial`, a stream without its last chunk is an error too.
+pub struct DecryptingReader<R: Read> {
+    inner: R,
+    cipher: Aes256Gcm,
+    header: Header,
+    partial: bool,
+    chunk: Vec<u8>,
+    position: usize,
+    counter: u32,
+    done: bool,
+}
+
+impl<R: Read> DecryptingReader<R> {
+    fn with_header(inner: R, key: &RecordingKey, header: Header, partial: bool) -> Result<Self> {
+        if header.salt != key.salt {
+            return Err(anyhow::anyhow!("File was encrypted with a different key"));
+        }
+        Ok(Self {
+            inner,
+            cipher: key.cipher.clone(),
+            header,
+            partial,
+            chunk: Vec::new(),
+            position: 0,
+            counter: 0,
+            done: false,
+        })
+    }
+
+    fn next_chunk(&mut self) -> std::io::Result<()> {
+        let mut len = [0u8; 4];
+        match self.inner.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof && self.partial => {
+                self.done = true;
+                return Ok(());
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                return Err(std::io::Error::new(e.kind(), "Encrypted file is truncated"))
+            }
+            Err(e) => return Err(e),
+        }
+        let len = u32::from_le_bytes(len) as usize;
+        if !(TAG_LEN..=CHUNK_SIZE + TAG_LEN).contains(&len) {
+            return Err(std::io::Error::other("Encrypted file is corrupt"));
+        }
+        let mut sealed = vec![0u8; len];
+        match self.inner.read_exact(&mut sealed) {
+            // A chunk torn by a crash, everything before it is intact
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof && self.partial => {
+                self.done = true;
+                return Ok(());
+            }
+            result => result?,
+        }
+
+        for last in [false, true] {
+            let nonce = self.header.nonce(self.counter, last);
+            let payload = Payload {
+                msg: &sealed,
+                aad: &self.header.bytes,
+            };
+            if let Ok(chunk) = self.cipher.decrypt(Nonce::from_slice(&nonce), payload) {
+                if last && self.inner.read(&mut [0u8; 1])? > 0 {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "Encrypted file has data after its end",
+                    ));
+                }
+                self.chunk = chunk;
+                self.position = 0;
+                self.counter += 1;
+                self.done = last;
+                return Ok(());
+            }
+        }
+        Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            if self.counter == 0 {
+                "Wrong passphrase or keyfile"
+            } else {
+                "Encrypted file has been modified"
+            },
+        ))
+    }
+}
+
+impl<R: Read> Read for DecryptingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.position == self.chunk.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.next_chunk()?;
+        }
+        let len = buf.len().min(self.chunk.len() - self.position);
+        buf[..len].copy_from_slice(&self.chunk[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+/// A flushed position in an [`OutputFile`], where [`InputFile::open_partial_at`] can
+/// start reading again
+#[derive(Clone, Copy, Debug)]
+pub struct SeekPoint {
+    offset: u64,
+    /// Index of the next encrypted chunk
+    counter: u32,
+}
+
+/// A file written in plaintext, or encrypted when the recording has a key
+pub enum OutputFile {
+    Plain(BufWriter<File>),
+    Encrypted(Box<EncryptedWriter<BufWriter<File>>>),
+}
+
+impl OutputFile {
+    pub fn create(path: &Path, key: Option<&RecordingKey>) -> Result<Self> {
+        let file = BufWriter::new(
+            File::create(path).with_context(|| format!("Failed to create {:?}", path))?,
+        );
+        Ok(match key {
+            Some(key) => OutputFile::Encrypted(Box::new(EncryptedWriter::new(file, key)?)),
+            None => OutputFile::Plain(file),
+        })
+    }
+
+    /// Flush and return the current position. Encrypted streams seal a chunk here, so
+    /// reading can resume without the bytes before it.
+    pub fn seek_point(&mut self) -> Result<SeekPoint> {
+        self.flush()?;
+        Ok(match self {
+            OutputFile::Plain(file) => SeekPoint {
+                offset: file.stream_position()?,
+                counter: 0,
+            },
+            OutputFile::Encrypted(writer) => SeekPoint {
+                offset: writer.offset,
+                counter: writer.counter,
+            },
+        })
+    }
+
+    pub fn finish(self) -> Result<()> {
+        match self {
+            OutputFile::Plain(mut file) => file.flush()?,
+            OutputFile::Encrypted(writer) => {
+                writer.finish()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        match self {
+            OutputFile::Plain(file) => file.write(data),
+            OutputFile::Encrypted(writer) => writer.write(data),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            OutputFile::Plain(file) => file.flush(),
+            OutputFile::Encrypted(writer) => writer.flush(),
+        }
+    }
+}
+
+/// Counterpart of [`OutputFile`]: reads a file the running recording wrote with `key`
+pub enum InputFile {
+    Plain(File),
+    Encrypted(Box<DecryptingReader<File>>),
+}
+
+impl InputFile {
+    /// Open a file written with `key`, or a plaintext one without
+    pub fn open(path: &Path, key: Option<&RecordingKey>) -> Result<Self> {
+        Self::open_with(path, key, false)
+    }
+
+    /// Like [`InputFile::open`] for a stream that is still being written, or was cut off
+    /// by a crash: reads up to the last complete chunk
+    pub fn open_partial(path: &Path, key: Option<&RecordingKey>) -> Result<Self> {
+        Self::open_with(path, key, true)
+    }
+
+    /// Like [`InputFile::open_partial`], starting at a point taken while writing
+    pub fn open_partial_at(
+        path: &Path,
+        key: Option<&RecordingKey>,
+        point: SeekPoint,
+    ) -> Result<Self> {
+        let mut input = Self::open_with(path, key, true)?;
+        match &mut input {
+            InputFile::Plain(file) => {
+                file.seek(SeekFrom::Start(point.offset))?;
+            }
+            InputFile::Encrypted(reader) => {
+                reader.inner.seek(SeekFrom::Start(point.offset))?;
+                reader.counter = point.counter;
+            }
+        }
+        Ok(input)
+    }
+
+    fn open_with(path: &Path, key: Option<&RecordingKey>, partial: bool) -> Result<Self> {
+        let mut file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+        let Some(key) = key else {
+            return Ok(InputFile::Plain(file));
+        };
+        let header =
+            Header::read(&mut file).with_context(|| format!("Failed to read {:?}", path))?;
+        let reader = DecryptingReader::with_header(file, key, header, partial)?;
+        Ok(InputFile::Encrypted(Box::new(reader)))
+    }
+}
+
+impl Read for InputFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            InputFile::Plain(file) => file.read(buf),
+            InputFile::Encrypted(reader) => reader.read(buf),
+        }
+    }
+}
+
+/// Write `data` to `path` in one go, encrypted when there is a key
+pub fn write_file(path: &Path, data: &[u8], key: Option<&RecordingKey>) -> Result<()> {
+    let mut file = OutputFile::create(path, key)?;
+    file.write_all(data)?;
+    file.finish()
+}
+
+/// Key for an existing recording's files, derived again from `source` once and then
+/// reused for every file that shares its salt
+pub struct Keyring {
+    source: KeySource,
+    key: Option<RecordingKey>,
+}
+
+impl Keyring {
+    pub fn new(source: KeySource) -> Self {
+        Self { source, key: None }
+    }
+
+    /// The key `path` was encrypted with. A newly derived key is checked against the
+    /// file's first chunk, so a wrong passphrase fails here rather than halfway through.
+    pub fn key_for(&mut self, path: &Path) -> Result<&RecordingKey> {
+        let mut file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+        let header =
+            Header::read(&mut file).with_context(|| format!("Failed to read {:?}", path))?;
+        let key = match self.key.take() {
+            Some(key) if key.salt == header.salt => key,
+            _ => {
+                let key = RecordingKey::unlock(&self.source, &header)?;
+                DecryptingReader::with_header(file, &key, header, true)?
+                    .read(&mut [0u8; 1])
+                    .with_context(|| format!("Failed to decrypt {:?}", path))?;
+                key
+            }
+        };
+        Ok(self.key.insert(key))
+    }
+
+    /// Decrypt `path` into `destination`, written to a temporary file first so a wrong
+    /// key or a damaged file never leaves half a copy behind
+    pub fn decrypt_to(&mut self, path: &Path, destination: &Path) -> Result<u64> {
+        let mut reader = InputFile::open(path, Some(self.key_for(path)?))?;
+        let mut tmp_path = destination.as_os_str().to_owned();
+        tmp_path.push(".decrypting");
+        let tmp_path = PathBuf::from(tmp_path);
+
+        let result = (|| -> Result<u64> {
+            let mut out = BufWriter::new(File::create(&tmp_path)?);
+            let bytes = std::io::copy(&mut reader, &mut out)
+                .with_context(|| format!("Failed to decrypt {:?}", path))?;
+            out.flush()?;
+            Ok(bytes)
+        })();
+        match result {
+            Ok(bytes) => {
+                std::fs::rename(&tmp_path, destination)?;
+                Ok(bytes)
+            }
+            Err(e) => {
+                let _ = std::fs::remove_file(&tmp_path);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Every file of a recording, with `<stem>.thumbs/` expanded
+fn expand(files: &[PathBuf]) -> Vec<PathBuf> {
+    let mut expanded = Vec::new();
+    for path in files {
+        if path.is_dir() {
+            if let Ok(read_dir) = std::fs::read_dir(path) {
+                let mut children: Vec<PathBuf> =
+                    read_dir.filter_map(|e| Some(e.ok()?.path())).collect();
+                children.sort();
+                expanded.extend(children.into_iter().filter(|p| p.is_file()));
+            }
+        } else {
+            expanded.push(path.clone());
+        }
+    }
+    expanded
+}
+
+/// Decrypt a recording's `files` where they are, plaintext files are left alone.
+/// Returns the files that were decrypted.
+pub fn decrypt_recording(files: &[PathBuf], source: KeySource) -> Result<Vec<PathBuf>> {
+    let mut keyring = Keyring::new(source);
+    let mut decrypted = Vec::new();
+    for path in expand(files).into_iter().filter(|p| is_encrypted(p)) {
+        keyring.decrypt_to(&path, &path)?;
+        decrypted.push(path);
+    }
+    tracing::info!("Decrypted {} files", decrypted.len());
+    Ok(decrypted)
+}
+
+/// Write decrypted copies of a recording's `files` into `destination`, keeping their
+/// names and the thumbnail folder. The recording itself stays encrypted.
+pub fn export_decrypted(
+    files: &[PathBuf],
+    source: KeySource,
+    destination: &Path,
+) -> Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(destination)?;
+    let mut keyring = Keyring::new(source);
+    let mut exported = Vec::new();
+    for path in files {
+        let Some(name) = path.file_name() else {
+            continue;
+        };
+        let target = destination.join(name);
+        if path.is_dir() {
+            std::fs::create_dir_all(&target)?;
+            for child in expand(std::slice::from_ref(path)) {
+                if let Some(child_name) = child.file_name() {
+                    exported.push(export_file(&mut keyring, &child, &target.join(child_name))?);
+                }
+            }
+        } else {
+            exported.push(export_file(&mut keyring, path, &target)?);
+        }
+    }
+    tracing::info!(
+        "Exported {} decrypted files to {:?}",
+        exported.len(),
+        destination
+    );
+    Ok(exported)
+}
+
+fn export_file(keyring: &mut Keyring, path: &Path, target: &Path) -> Result<PathBuf> {
+    if target.exists() {
+        return Err(anyhow::anyhow!("{:?} already exists", target));
+    }
+    if is_encrypted(path) {
+        keyring.decrypt_to(path, target)?;
+    } else {
+        std::fs::copy(path, target)?;
+    }
+    Ok(target.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("encryption_test_{}_{}", std::process::id(), name))
+    }
+
+    /// A key from a fresh random keyfile, passphrases are slow to derive
+    fn keyfile(name: &str) -> KeySource {
+        let path = temp_path(name);
+        let mut contents = [0u8; 32];
+        random(&mut contents).unwrap();
+        std::fs::write(&path, contents).unwrap();
+        KeySource::Keyfile { path }
+    }
+
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn encrypt(key: &RecordingKey, data: &[u8]) -> Vec<u8> {
+        let mut writer = EncryptedWriter::new(Vec::new(), key).unwrap();
+        writer.write_all(data).unwrap();
+        writer.finish().unwrap()
+    }
+
+    fn decrypt(key: &RecordingKey, bytes: &[u8], partial: bool) -> Result<Vec<u8>> {
+        let mut cursor = Cursor::new(bytes);
+        let header = Header::read(&mut cursor)?;
+        let mut reader = DecryptingReader::with_header(cursor, key, header, partial)?;
+        let mut plain = Vec::new();
+        reader.read_to_end(&mut plain)?;
+        Ok(plain)
+    }
+
+    /// Header and the length-prefixed chunks after it
+    fn split(bytes: &[u8]) -> (Vec<u8>, Vec<Vec<u8>>) {
+        let mut chunks = Vec::new();
+        let mut rest = &bytes[HEADER_LEN..];
+        while !rest.is_empty() {
+            let len = 4 + u32::from_le_bytes(rest[..4].try_into().unwrap()) as usize;
+            chunks.push(rest[..len].to_vec());
+            rest = &rest[len..];
+        }
+        (bytes[..HEADER_LEN].to_vec(), chunks)
+    }
+
+    fn join(header: Vec<u8>, chunks: Vec<Vec<u8>>) -> Vec<u8> {
+        header
+            .into_iter()
+            .chain(chunks.into_iter().flatten())
+            .collect()
+    }
+
+    #[test]
+    fn round_trips() {
+        let key = RecordingKey::new(&keyfile("round_trip")).unwrap();
+        for len in [0, 1, CHUNK_SIZE, 3 * CHUNK_SIZE + 100] {
+            let plain = data(len);
+            let bytes = encrypt(&key, &plain);
+            assert_eq!(split(&bytes).1.len(), len / CHUNK_SIZE + 1);
+            assert_eq!(decrypt(&key, &bytes, false).unwrap(), plain);
+        }
+    }
+
+    #[test]
+    fn rejects_wrong_key() {
+        let key = RecordingKey::new(&keyfile("right")).unwrap();
+        let bytes = encrypt(&key, &data(100));
+
+        let other = RecordingKey::new(&keyfile("other")).unwrap();
+        assert!(decrypt(&other, &bytes, false).is_err());
+        // Same salt, different keyfile contents
+        let other = RecordingKey::derive(&keyfile("other_same_salt"), key.salt).unwrap();
+        let error = decrypt(&other, &bytes, false).unwrap_err();
+        assert!(error.to_string().contains("Wrong passphrase or keyfile"));
+    }
+
+    #[test]
+    fn rejects_flipped_byte() {
+        let key = RecordingKey::new(&keyfile("flipped")).unwrap();
+        let bytes = encrypt(&key, &data(2 * CHUNK_SIZE));
+        for index in [10, HEADER_LEN + 100, bytes.len() - 1] {
+            let mut tampered = bytes.clone();
+            tampered[index] ^= 1;
+            assert!(decrypt(&key, &tampered, false).is_err());
+            assert!(decrypt(&key, &tampered, true).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_dropped_last_chunk() {
+        let key = RecordingKey::new(&keyfile("dropped")).unwrap();
+        let (header, mut chunks) = split(&encrypt(&key, &data(2 * CHUNK_SIZE + 10)));
+        chunks.pop();
+        let bytes = join(header, chunks);
+        assert!(decrypt(&key, &bytes, false).is_err());
+        // Read as an unfinished stream, the complete chunks are still there
+        assert_eq!(decrypt(&key, &bytes, true).unwrap(), data(2 * CHUNK_SIZE));
+    }
+
+    #[test]
+    fn rejects_reordered_chunks() {
+        let key = RecordingKey::new(&keyfile("reordered")).unwrap();
+        let (header, mut chunks) = split(&encrypt(&key, &data(2 * CHUNK_SIZE + 10)));
+        chunks.swap(0, 1);
+        let bytes = join(header, chunks);
+        assert!(decrypt(&key, &bytes, false).is_err());
+        assert!(decrypt(&key, &bytes, true).is_err());
+    }
+
+    #[test]
+    fn rejects_trailing_data() {
+        let key = RecordingKey::new(&keyfile("trailing")).unwrap();
+        let mut bytes = encrypt(&key, &data(100));
+        bytes.push(0);
+        assert!(decrypt(&key, &bytes, false).is_err());
+        assert!(decrypt(&key, &bytes, true).is_err());
+    }
+
+    #[test]
+    fn open_partial_reads_up_to_torn_chunk() {
+        let key = RecordingKey::new(&keyfile("torn")).unwrap();
+        let path = temp_path("torn.mkv");
+        let mut output = OutputFile::create(&path, Some(&key)).unwrap();
+        output.write_all(&data(1000)).unwrap();
+        output.flush().unwrap();
+        output.write_all(&data(500)).unwrap();
+        output.flush().unwrap();
+        // A crash halfway through writing the second chunk
+        drop(output);
+        let len = std::fs::metadata(&path).unwrap().len();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 10)
+            .unwrap();
+
+        let mut plain = Vec::new();
+        InputFile::open_partial(&path, Some(&key))
+            .unwrap()
+            .read_to_end(&mut plain)
+            .unwrap();
+        assert_eq!(plain, data(1000));
+        let mut input = InputFile::open(&path, Some(&key)).unwrap();
+        assert!(input.read_to_end(&mut Vec::new()).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn flush_then_finish() {
+        let key = RecordingKey::new(&keyfile("flush")).unwrap();
+        let mut writer = EncryptedWriter::new(Vec::new(), &key).unwrap();
+        writer.write_all(&data(1000)).unwrap();
+        writer.flush().unwrap();
+        // Nothing buffered, no empty chunk
+        writer.flush().unwrap();
+        let bytes = writer.finish().unwrap();
+        // The flushed chunk and an empty last one
+        assert_eq!(split(&bytes).1.len(), 2);
+        assert_eq!(decrypt(&key, &bytes, false).unwrap(), data(1000));
+    }
+}
@@ -0,0 +1,97 @@
+use crate::analytics::SilenceSpan;
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+/// Cut the silence spans recorded in the sidecar out of a recording. Only recordings
+/// analyzed with real audio levels have spans to cut.
+///
+/// Audio and video are trimmed with the same select expression so they stay in sync.
+/// Requires an `ffmpeg` binary on the PATH.
+pub async fn export_trimmed(video_path: &Path) -> Result<PathBuf> {
+    let mut meta_path = video_path.to_path_buf();
+    meta_path.set_extension("meta.json");
+    let metadata: Value = serde_json::from_str(
+        &std::fs::read_to_string(&meta_path)
+            .with_context(|| format!("Failed to read metadata {:?}", meta_path))?,
+    )?;
+
+    if metadata["audio"].as_bool() != Some(true) {
+        return Err(anyhow::anyhow!(
+            "Recording has no audio levels, there is no silence to trim"
+        ));
+    }
+
+    let duration = metadata["duration"].as_f64().unwrap_or(0.0);
+    let spans: Vec<SilenceSpan> =
+        serde_json::from_value(metadata["silence_spans"].clone()).unwrap_or_default();
+
+    let keep = keep_ranges(&spans, duration);
+    if keep.is_empty() {
+        return Err(anyhow::anyhow!("Nothing left to export after trimming silence"));
+    }
+
+    let select = keep
+        .iter()
+        .map(|(start, end)| format!("between(t,{:.3},{:.3})", start, end))
+        .collect::<Vec<_>>()
+        .join("+");
+
+    let output_path = trimmed_output_path(video_path);
+    let status = Command::new("ffmpeg")
+        .arg("-y")
+        .arg("-i")
+        .arg(video_path)
+        .arg("-vf")
+        .arg(format!("select='{}',setpts=N/FRAME_RATE/TB", select))
+        .arg("-af")
+        .arg(format!("aselect='{}',asetpts=N/SR/TB", select))
+        .arg(&output_path)
+        .status()
+        .await
+        .context("Failed to run ffmpeg")?;
+
+    if !status.success() {
+        return Err(anyhow::anyhow!("ffmpeg exited with {}", status));
+    }
+
+    tracing::info!(
+        "Exported {:?} with {} silence spans removed",
+        output_path,
+        spans.len()
+    );
+    Ok(output_path)
+}
+
+/// Invert silence spans into the ranges of `[0, duration]` that should be kept
+fn keep_ranges(spans: &[SilenceSpan], duration: f64) -> Vec<(f64, f64)> {
+    let mut sorted = spans.to_vec();
+    sorted.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+    let mut ranges = Vec::new();
+    let mut cursor = 0.0;
+    for span in sorted {
+        if span.start > cursor {
+            ranges.push((cursor, span.start.min(duration)));
+        }
+        cursor = f64::max(cursor, span.end);
+    }
+    if cursor < duration {
+        ranges.push((cursor, duration));
+    }
+    ranges.retain(|(start, end)| end > start);
+    ranges
+}
+
+fn trimmed_output_path(video_path: &Path) -> PathBuf {
+    let stem = video_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("recording");
+    let extension = video_path
+        .extension()
+        .and_then(|s| s.to_str())
+        .unwrap_or("mkv");
+    video_path.with_file_name(format!("{}_trimmed.{}", stem, extension))
+}
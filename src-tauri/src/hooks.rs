@@ -0,0 +1,622 @@
+use crate::encryption;
+use crate::integrity::{self, sha256_file};
+use crate::session;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tauri::{AppHandle, Emitter};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::process::Command;
+use tokio::time::Duration;
+
+/// One post-processing step, run in order after a recording is finalized.
+///
+/// Command arguments, transcode arguments and webhook URLs are templates: `{path}`,
+/// `{meta_path}`, `{dir}`, `{session_id}`, `{duration}` and `{last_output}` (the file
+/// written by the latest transcode or thumbnail step, the recording itself before that).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HookStep {
+    pub name: String,
+    #[serde(flatten)]
+    pub action: HookAction,
+    /// Extra attempts after the first one fails or times out
+    #[serde(default)]
+    pub retries: u32,
+    #[serde(default = "default_timeout")]
+    pub timeout_seconds: u64,
+    /// Keep running the remaining steps when this one fails for good
+    #[serde(default)]
+    pub continue_on_error: bool,
+}
+
+fn default_timeout() -> u64 {
+    300
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HookAction {
+    /// Run an external program, a non-zero exit status fails the step
+    Command { program: String, args: Vec<String> },
+    /// Re-encode into `<recording>.transcoded.<container>`, segments are joined
+    Transcode {
+        #[serde(default = "default_container")]
+        container: String,
+        /// ffmpeg output options, H.264/AAC by default
+        #[serde(default)]
+        args: Vec<String>,
+    },
+    /// Grab one frame into `<recording>.poster.jpg`
+    Thumbnail {
+        #[serde(default = "default_thumbnail_time")]
+        time: f64,
+        #[serde(default = "default_thumbnail_width")]
+        width: u32,
+    },
+    /// SHA-256 of every video file, written to `<recording>.sha256` in `sha256sum` format
+    Checksum,
+    /// POST the session summary and the results so far as JSON. Plain `http://` only.
+    Webhook { url: String },
+}
+
+fn default_container() -> String {
+    "mp4".to_string()
+}
+
+fn default_thumbnail_time() -> f64 {
+    1.0
+}
+
+fn default_thumbnail_width() -> u32 {
+    640
+}
+
+/// What the steps of one run get to work with
+#[derive(Clone, Debug, Serialize)]
+pub struct HookContext {
+    pub session_id: String,
+    pub video_path: PathBuf,
+    pub meta_path: PathBuf,
+    pub duration: f64,
+    /// The video file, or its segments in order
+    pub inputs: Vec<PathBuf>,
+}
+
+impl HookContext {
+    /// Context for a finalized recording from its segment manifest
+    pub fn from_manifest(video_path: &Path, manifest: &Value) -> Self {
+        let mut inputs: Vec<PathBuf> = manifest["segments"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|s| s["path"].as_str().map(PathBuf::from))
+            .collect();
+        if inputs.is_empty() {
+            inputs.push(video_path.to_path_buf());
+        }
+        Self::new(
+            video_path,
+            manifest["duration"].as_f64().unwrap_or(0.0),
+            inputs,
+        )
+    }
+
+    /// Context for any recording in the library, from its sidecar and segment manifest
+    pub fn from_recording(video_path: &Path) -> Result<Self> {
+        if encryption::is_encrypted(&video_path.with_extension("meta.json")) {
+            return Err(anyhow::anyhow!(
+                "Recording is encrypted, decrypt it before post-processing"
+            ));
+        }
+        let mut manifest_path = video_path.to_path_buf();
+        manifest_path.set_extension("manifest.json");
+        if let Ok(manifest) = std::fs::read_to_string(&manifest_path) {
+            return Ok(Self::from_manifest(
+                video_path,
+                &serde_json::from_str(&manifest)?,
+            ));
+        }
+        if !video_path.exists() {
+            return Err(anyhow::anyhow!("Recording {:?} not found", video_path));
+        }
+
+        let mut context = Self::new(video_path, 0.0, vec![video_path.to_path_buf()]);
+        if let Ok(metadata) = std::fs::read_to_string(&context.meta_path) {
+            let metadata: Value = serde_json::from_str(&metadata)?;
+            context.duration = metadata["duration"].as_f64().unwrap_or(0.0);
+        }
+        Ok(context)
+    }
+
+    fn new(video_path: &Path, duration: f64, inputs: Vec<PathBuf>) -> Self {
+        let mut meta_path = video_path.to_path_buf();
+        meta_path.set_extension("meta.json");
+        Self {
+            session_id: video_path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            video_path: video_path.to_path_buf(),
+            meta_path,
+            duration,
+            inputs,
+        }
+    }
+
+    fn render(&self, template: &str, last_output: &Path) -> String {
+        let dir = self.video_path.parent().unwrap_or(Path::new(""));
+        template
+            .replace("{path}", &self.video_path.to_string_lossy())
+            .replace("{meta_path}", &self.meta_path.to_string_lossy())
+            .replace("{dir}", &dir.to_string_lossy())
+            .replace("{session_id}", &self.session_id)
+            .replace("{duration}", &format!("{:.3}", self.duration))
+            .replace("{last_output}", &last_output.to_string_lossy())
+    }
+
+    /// `<recording>.<suffix>` next to the recording
+    fn output_path(&self, suffix: &str) -> PathBuf {
+        let mut path = self.video_path.clone();
+        path.set_extension(suffix);
+        path
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    Ok,
+    Failed,
+    TimedOut,
+    /// An earlier step failed without `continue_on_error`
+    Skipped,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct StepResult {
+    pub name: String,
+    pub status: StepStatus,
+    pub attempts: u32,
+    pub started_at: Option<String>,
+    pub duration_seconds: f64,
+    pub output: Option<Value>,
+    pub error: Option<String>,
+}
+
+fn hooks_path() -> Result<PathBuf> {
+    Ok(session::config_dir()?.join("hooks.json"))
+}
+
+/// Configured steps, none until the user adds some
+pub fn load_steps() -> Result<Vec<HookStep>> {
+    let path = hooks_path()?;
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse hooks {:?}", path)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn save_steps(steps: &[HookStep]) -> Result<()> {
+    for step in steps {
+        if step.name.trim().is_empty() {
+            return Err(anyhow::anyhow!("Every hook step needs a name"));
+        }
+        if step.timeout_seconds == 0 {
+            return Err(anyhow::anyhow!("Hook {:?} needs a timeout", step.name));
+        }
+        if let HookAction::Webhook { url } = &step.action {
+            if !url.starts_with("http://") {
+                return Err(anyhow::anyhow!(
+                    "Hook {:?}: only http:// webhooks are supported",
+                    step.name
+                ));
+            }
+        }
+    }
+    std::fs::write(hooks_path()?, serde_json::to_vec_pretty(steps)?)?;
+    Ok(())
+}
+
+/// Run the configured steps for a just finalized recording
+pub async fn run_configured(context: HookContext, app: AppHandle) {
+    // The steps would only see ciphertext
+    if encryption::is_encrypted(&context.meta_path) {
+        tracing::info!("Skipping post-processing for encrypted {:?}", context.video_path);
+        return;
+    }
+    let steps = match load_steps() {
+        Ok(steps) if steps.is_empty() => return,
+        Ok(steps) => steps,
+        Err(e) => {
+            tracing::error!("Failed to load post-processing hooks: {}", e);
+            return;
+        }
+    };
+    run(&context, &steps, Some(&app)).await;
+}
+
+/// Run `steps` in order and store the results in the recording's sidecar under
+/// `post_processing`. Emits `post-processing-update` after every step.
+pub async fn run(context: &HookContext, steps: &[HookStep], app: Option<&AppHandle>) -> Value {
+    let started_at = chrono::Local::now().to_rfc3339();
+    let mut results: Vec<StepResult> = Vec::new();
+    let mut last_output = context.video_path.clone();
+    let mut aborted = false;
+
+    for step in steps {
+        let result = if aborted {
+            StepResult {
+                name: step.name.clone(),
+                status: StepStatus::Skipped,
+                attempts: 0,
+                started_at: None,
+                duration_seconds: 0.0,
+                output: None,
+                error: None,
+            }
+        } else {
+            run_step(context, step, &last_output, &results).await
+        };
+
+        if result.status == StepStatus::Ok {
+            if let Some(path) = result.output.as_ref().and_then(|o| o["path"].as_str()) {
+                last_output = PathBuf::from(path);
+            }
+        } else if result.status != StepStatus::Skipped && !step.continue_on_error {
+            aborted = true;
+        }
+
+        if let Some(app) = app {
+            let _ = app.emit(
+                "post-processing-update",
+                json!({
+                    "session_id": context.session_id,
+                    "step": result,
+                    "index": results.len(),
+                    "total": steps.len(),
+                }),
+            );
+        }
+        results.push(result);
+    }
+
+    let report = json!({
+        "started_at": started_at,
+        "finished_at": chrono::Local::now().to_rfc3339(),
+        "succeeded": !aborted && results.iter().all(|r| r.status == StepStatus::Ok),
+        "steps": results,
+    });
+    let rewritten = [context.meta_path.clone()];
+    let stored = match integrity::check_unchanged(&context.video_path, &rewritten).await {
+        Ok(()) => store_report(&context.meta_path, &report),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = stored {
+        tracing::warn!("Failed to store post-processing results: {}", e);
+    } else if let Err(e) = integrity::update(&context.video_path, &rewritten).await {
+        tracing::warn!("Failed to reseal {:?}: {}", context.video_path, e);
+    }
+    tracing::info!("Post-processing of {} finished", context.session_id);
+    report
+}
+
+async fn run_step(
+    context: &HookContext,
+    step: &HookStep,
+    last_output: &Path,
+    previous: &[StepResult],
+) -> StepResult {
+    let started_at = chrono::Local::now();
+    let timeout = Duration::from_secs(step.timeout_seconds);
+    let mut attempts = 0;
+    let (status, output, error) = loop {
+        attempts += 1;
+        let attempt = tokio::time::timeout(
+            timeout,
+            run_action(context, &step.action, last_output, previous),
+        )
+        .await;
+        let (status, output, error) = match attempt {
+            Ok(Ok(output)) => break (StepStatus::Ok, Some(output), None),
+            Ok(Err(e)) => (StepStatus::Failed, None, Some(e.to_string())),
+            Err(_) => (
+                StepStatus::TimedOut,
+                None,
+                Some(format!("Timed out after {}s", step.timeout_seconds)),
+            ),
+        };
+        tracing::warn!(
+            "Hook {:?} attempt {} failed: {}",
+            step.name,
+            attempts,
+            error.as_deref().unwrap_or("")
+        );
+        if attempts > step.retries {
+            break (status, output, error);
+        }
+        tokio::time::sleep(Duration::from_secs(2 * attempts as u64)).await;
+    };
+
+    StepResult {
+        name: step.name.clone(),
+        status,
+        attempts,
+        started_at: Some(started_at.to_rfc3339()),
+        duration_seconds: (chrono::Local::now() - started_at).num_milliseconds() as f64 / 1000.0,
+        output,
+        error,
+    }
+}
+
+async fn run_action(
+    context: &HookContext,
+    action: &HookAction,
+    last_output: &Path,
+    previous: &[StepResult],
+) -> Result<Value> {
+    match action {
+        HookAction::Command { program, args } => {
+            let args: Vec<String> = args
+                .iter()
+                .map(|a| context.render(a, last_output))
+                .collect();
+            let output = Command::new(context.render(program, last_output))
+                .args(&args)
+                .stdin(Stdio::null())
+                .kill_on_drop(true)
+                .output()
+                .await
+                .with_context(|| format!("Failed to run {:?}", program))?;
+            let stdout = tail(&output.stdout);
+            if !output.status.success() {
+                return Err(anyhow::anyhow!(
+                    "{} exited with {}: {}",
+                    program,
+                    output.status,
+                    tail(&output.stderr)
+                ));
+            }
+            Ok(json!({ "exit_code": output.status.code(), "stdout": stdout }))
+        }
+        HookAction::Transcode { container, args } => {
+            let output_path = context.output_path(&format!("transcoded.{}", container));
+            let args: Vec<String> = if args.is_empty() {
+                ["-c:v", "libx264", "-crf", "23", "-c:a", "aac"]
+                    .map(String::from)
+                    .to_vec()
+            } else {
+                args.iter()
+                    .map(|a| context.render(a, last_output))
+                    .collect()
+            };
+            let mut command = Command::new("ffmpeg");
+            command.args(["-y", "-v", "error"]);
+
+            // Segments are joined with the concat demuxer, which needs a list file
+            let concat_path = context.output_path("concat.txt");
+            if context.inputs.len() > 1 {
+                let list: String = context
+                    .inputs
+                    .iter()
+                    .map(|p| format!("file '{}'\n", p.to_string_lossy().replace('\'', "'\\''")))
+                    .collect();
+                std::fs::write(&concat_path, list)?;
+                command
+                    .args(["-f", "concat", "-safe", "0", "-i"])
+                    .arg(&concat_path);
+            } else {
+                command.arg("-i").arg(&context.inputs[0]);
+            }
+
+            let status = command
+                .args(&args)
+                .arg(&output_path)
+                .kill_on_drop(true)
+                .status()
+                .await
+                .context("Failed to run ffmpeg");
+            let _ = std::fs::remove_file(&concat_path);
+            if !status?.success() {
+                let _ = std::fs::remove_file(&output_path);
+                return Err(anyhow::anyhow!("ffmpeg failed to transcode"));
+            }
+            let bytes = std::fs::metadata(&output_path)?.len();
+            Ok(json!({ "path": output_path, "bytes": bytes }))
+        }
+        HookAction::Thumbnail { time, width } => {
+            let output_path = context.output_path("poster.jpg");
+            let time = time.min(context.duration).max(0.0);
+            let status = Command::new("ffmpeg")
+                .args(["-y", "-v", "error", "-ss"])
+                .arg(format!("{:.3}", time))
+                .arg("-i")
+                .arg(&context.inputs[0])
+                .args(["-frames:v", "1", "-vf"])
+                .arg(format!("scale={}:-2", width))
+                .arg(&output_path)
+                .kill_on_drop(true)
+                .status()
+                .await
+                .context("Failed to run ffmpeg")?;
+            if !status.success() {
+                return Err(anyhow::anyhow!("ffmpeg exited with {}", status));
+            }
+            Ok(json!({ "path": output_path, "time": time }))
+        }
+        HookAction::Checksum => {
+            let mut files = context.inputs.clone();
+            if last_output != context.video_path && !files.iter().any(|f| f == last_output) {
+                files.push(last_output.to_path_buf());
+            }
+            let hashes = tokio::task::spawn_blocking(move || -> Result<Vec<(PathBuf, String)>> {
+                files
+                    .into_iter()
+                    .map(|path| Ok((path.clone(), sha256_file(&path)?)))
+                    .collect()
+            })
+            .await??;
+
+            let checksum_path = context.output_path("sha256");
+            let listing: String = hashes
+                .iter()
+                .map(|(path, hash)| {
+                    let name = path.file_name().unwrap_or_default().to_string_lossy();
+                    format!("{}  {}\n", hash, name)
+                })
+                .collect();
+            std::fs::write(&checksum_path, listing)?;
+            let files: serde_json::Map<String, Value> = hashes
+                .into_iter()
+                .map(|(path, hash)| (path.to_string_lossy().to_string(), json!(hash)))
+                .collect();
+            Ok(json!({ "checksum_path": checksum_path, "sha256": files }))
+        }
+        HookAction::Webhook { url } => {
+            let body = json!({
+                "session": context,
+                "steps": previous,
+            });
+            let status = post_json(&context.render(url, last_output), &body).await?;
+            Ok(json!({ "status": status }))
+        }
+    }
+}
+
+/// Last lines of a process' output, enough to see why it failed
+fn tail(output: &[u8]) -> String {
+    let text = String::from_utf8_lossy(output);
+    let lines: Vec<&str> = text.trim_end().lines().collect();
+    lines[lines.len().saturating_sub(20)..].join("\n")
+}
+
+/// Minimal HTTP/1.1 POST, returns the response status. Webhooks are local services, so
+/// there is no TLS or redirect handling.
+async fn post_json(url: &str, body: &Value) -> Result<u16> {
+    let (host, port, path) = parse_http_url(url)?;
+
+    let body = body.to_string();
+    let mut stream = TcpStream::connect((host.as_str(), port))
+        .await
+        .with_context(|| format!("Failed to connect to {}:{}", host, port))?;
+    let host_header = match (host.contains(':'), port) {
+        (true, _) => format!("[{}]:{}", host, port),
+        (false, 80) => host.clone(),
+        (false, _) => format!("{}:{}", host, port),
+    };
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        path,
+        host_header,
+        body.len()
+    );
+    stream.write_all(request.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+
+    let mut status_line = String::new();
+    let read = BufReader::new(stream)
+        .take(1024)
+        .read_line(&mut status_line)
+        .await
+        .context("Failed to read the webhook response")?;
+    if read == 0 {
+        return Err(anyhow::anyhow!("Webhook closed the connection without a response"));
+    }
+    if !status_line.ends_with('\n') {
+        return Err(anyhow::anyhow!(
+            "Incomplete HTTP status line: {:?}",
+            status_line
+        ));
+    }
+    let mut parts = status_line.split_whitespace();
+    let status = match (parts.next(), parts.next()) {
+        (Some(version), Some(code)) if version.starts_with("HTTP/1.") && code.len() == 3 => {
+            code.parse::<u16>().ok()
+        }
+        _ => None,
+    }
+    .filter(|code| (100..600).contains(code))
+    .ok_or_else(|| anyhow::anyhow!("Invalid HTTP response: {:?}", status_line.trim()))?;
+    if !(200..300).contains(&status) {
+        return Err(anyhow::anyhow!("Webhook returned HTTP {}", status));
+    }
+    Ok(status)
+}
+
+/// Host, port and request target of an `http://host[:port][/path][?query]` URL. The
+/// target is percent-encoded where rendered placeholders left spaces or non-ASCII.
+fn parse_http_url(url: &str) -> Result<(String, u16, String)> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| anyhow::anyhow!("Only http:// webhooks are supported: {}", url))?;
+    let rest = rest.split('#').next().unwrap_or_default();
+    let (authority, target) = match rest.find(['/', '?']) {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    if authority.contains('@') {
+        return Err(anyhow::anyhow!("Webhook URLs can't carry credentials: {}", url));
+    }
+
+    // Bracketed IPv6 literal, or a name or IPv4 address with an optional port
+    let (host, port) = match authority.strip_prefix('[') {
+        Some(bracketed) => {
+            let (host, after) = bracketed
+                .split_once(']')
+                .ok_or_else(|| anyhow::anyhow!("Invalid webhook host: {}", url))?;
+            (host, after.strip_prefix(':'))
+        }
+        None => match authority.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        },
+    };
+    let valid_host = !host.is_empty()
+        && host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | ':'));
+    if !valid_host {
+        return Err(anyhow::anyhow!("Invalid webhook host {:?}", host));
+    }
+    let port = match port {
+        None => 80,
+        Some(port) => port
+            .parse::<u16>()
+            .ok()
+            .filter(|p| *p > 0)
+            .ok_or_else(|| anyhow::anyhow!("Invalid webhook port {:?}", port))?,
+    };
+
+    let mut path = String::new();
+    if target.starts_with('?') {
+        path.push('/');
+    }
+    for byte in target.bytes() {
+        if byte.is_ascii_graphic() {
+            path.push(byte as char);
+        } else {
+            path.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    Ok((host.to_string(), port, path))
+}
+
+/// Put the results into the sidecar next to the analytics
+fn store_report(meta_path: &Path, report: &Value) -> Result<()> {
+    let mut metadata: Value = serde_json::from_str(
+        &std::fs::read_to_string(meta_path)
+            .with_context(|| format!("Failed to read metadata {:?}", meta_path))?,
+    )?;
+    metadata["post_processing"] = report.clone();
+
+    // Written aside first so a crash mid-write never leaves a torn sidecar
+    let mut tmp_path = meta_path.to_path_buf();
+    tmp_path.set_extension("json.tmp");
+    std::fs::write(&tmp_path, serde_json::to_vec_pretty(&metadata)?)?;
+    std::fs::rename(&tmp_path, meta_path)?;
+    Ok(())
+}
@@ -0,0 +1,394 @@
+use crate::session;
+use anyhow::{Context, Result};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+const MANIFEST_VERSION: u32 = 1;
+
+/// `<recording>.integrity.json`: digests of every file of a finished recording, signed
+/// with the app's local ed25519 key
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IntegrityManifest {
+    pub payload: ManifestPayload,
+    /// Hex ed25519 public key the payload was signed with
+    pub public_key: String,
+    /// Hex ed25519 signature over the JSON serialization of `payload`
+    pub signature: String,
+}
+
+/// The signed part. Plain structs rather than JSON maps, so serializing it again when
+/// verifying gives back exactly the signed bytes.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ManifestPayload {
+    pub version: u32,
+    pub session: SessionRecord,
+    pub files: Vec<FileDigest>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionRecord {
+    pub session_id: String,
+    pub video_path: String,
+    pub duration: f64,
+    pub source: Option<String>,
+    pub segments: usize,
+    pub recovered: bool,
+    pub sealed_at: String,
+}
+
+/// A file of the recording, by name relative to the recording's folder
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileDigest {
+    pub name: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+fn manifest_path(video_path: &Path) -> PathBuf {
+    let mut path = video_path.to_path_buf();
+    path.set_extension("integrity.json");
+    path
+}
+
+/// Hex SHA-256 of a file, streamed in 1 MB chunks
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut file =
+        std::fs::File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 1024 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// The app's signing key, generated on first use and kept in the config directory
+fn signing_key() -> Result<SigningKey> {
+    if let Some(key) = load_signing_key()? {
+        return Ok(key);
+    }
+    let path = signing_key_path()?;
+    let mut seed = [0u8; 32];
+    getrandom::getrandom(&mut seed)
+        .map_err(|e| anyhow::anyhow!("Failed to generate signing key: {}", e))?;
+    write_private(&path, &seed)?;
+    tracing::info!("Generated recording signing key {:?}", path);
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+/// The app's signing key if one was generated already. Checking a recording never
+/// creates one.
+fn load_signing_key() -> Result<Option<SigningKey>> {
+    let path = signing_key_path()?;
+    match std::fs::read(&path) {
+        Ok(seed) => {
+            let seed: [u8; 32] = seed
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Signing key {:?} is corrupt", path))?;
+            Ok(Some(SigningKey::from_bytes(&seed)))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn signing_key_path() -> Result<PathBuf> {
+    Ok(session::config_dir()?.join("integrity_key"))
+}
+
+#[cfg(unix)]
+fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(contents)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    std::fs::write(path, contents)?;
+    Ok(())
+}
+
+/// Files covered by the manifest: the video (or its segments) followed by the sidecar and
+/// the segment manifest, whichever exist. Also returns how many are video files.
+fn recording_files(video_path: &Path) -> Result<(Vec<PathBuf>, usize)> {
+    let mut segment_manifest = video_path.to_path_buf();
+    segment_manifest.set_extension("manifest.json");
+    let mut meta_path = video_path.to_path_buf();
+    meta_path.set_extension("meta.json");
+
+    let mut files = Vec::new();
+    if let Ok(manifest) = std::fs::read_to_string(&segment_manifest) {
+        let manifest: Value = serde_json::from_str(&manifest)?;
+        let segments = manifest["segments"].as_array().into_iter().flatten();
+        files.extend(
+            segments
+                .filter_map(|s| s["path"].as_str().map(PathBuf::from))
+                .filter(|p| p != video_path),
+        );
+    }
+    files.insert(0, video_path.to_path_buf());
+    files.retain(|p| p.exists());
+    if files.is_empty() {
+        return Err(anyhow::anyhow!("Recording {:?} not found", video_path));
+    }
+    let videos = files.len();
+    files.extend(
+        [meta_path, segment_manifest]
+            .into_iter()
+            .filter(|p| p.exists()),
+    );
+    Ok((files, videos))
+}
+
+/// Hash and sign a finished recording, replacing any previous manifest. Hashing runs on
+/// a blocking thread, it reads every byte of the recording.
+pub async fn seal(video_path: &Path) -> Result<PathBuf> {
+    let video_path = video_path.to_path_buf();
+    tokio::task::spawn_blocking(move || seal_blocking(&video_path)).await?
+}
+
+/// Fail if any of `files` changed since the recording was sealed, so the app doesn't
+/// build on tampered files it is about to rewrite. Files the manifest doesn't cover are
+/// skipped, unsealed recordings always pass.
+pub async fn check_unchanged(video_path: &Path, files: &[PathBuf]) -> Result<()> {
+    let video_path = video_path.to_path_buf();
+    let files = files.to_vec();
+    tokio::task::spawn_blocking(move || check_unchanged_blocking(&video_path, &files)).await?
+}
+
+fn check_unchanged_blocking(video_path: &Path, files: &[PathBuf]) -> Result<()> {
+    let Some(manifest) = trusted_manifest(video_path)? else {
+        return Ok(());
+    };
+    for path in files {
+        let name = file_name(path);
+        let Some(expected) = manifest.payload.files.iter().find(|f| f.name == name) else {
+            continue;
+        };
+        let status = file_status(path, expected);
+        if status != "ok" {
+            return Err(anyhow::anyhow!(
+                "{:?} is {} since the recording was sealed",
+                path,
+                status
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Reseal after the app itself rewrote `changed` files of a sealed recording (chapter
+/// edits, post-processing results, decryption). Only those are hashed again, so changes
+/// made to the other files behind the app's back still show up as tampering.
+pub async fn update(video_path: &Path, changed: &[PathBuf]) -> Result<()> {
+    let video_path = video_path.to_path_buf();
+    let changed = changed.to_vec();
+    tokio::task::spawn_blocking(move || update_blocking(&video_path, &changed)).await?
+}
+
+fn update_blocking(video_path: &Path, changed: &[PathBuf]) -> Result<()> {
+    let Some(mut manifest) = trusted_manifest(video_path)? else {
+        return Ok(());
+    };
+    for digest in &mut manifest.payload.files {
+        if let Some(path) = changed.iter().find(|p| file_name(p) == digest.name) {
+            *digest = file_digest(path)?;
+        }
+    }
+    manifest.payload.session.sealed_at = chrono::Local::now().to_rfc3339();
+    write_signed(video_path, manifest.payload, &signing_key()?)?;
+    Ok(())
+}
+
+/// The recording's manifest, `None` if it was never sealed. Fails unless the signature
+/// holds and was made with this machine's key, nothing is resealed on top of a manifest
+/// the app can't vouch for.
+fn trusted_manifest(video_path: &Path) -> Result<Option<IntegrityManifest>> {
+    let path = manifest_path(video_path);
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let manifest: IntegrityManifest = serde_json::from_str(&contents)
+        .with_context(|| format!("Integrity manifest {:?} is corrupt", path))?;
+    if !check_signature(&manifest)? {
+        return Err(anyhow::anyhow!(
+            "Integrity manifest {:?} has an invalid signature",
+            path
+        ));
+    }
+    if !is_trusted(&manifest) {
+        return Err(anyhow::anyhow!(
+            "Integrity manifest {:?} was not signed with this machine's key",
+            path
+        ));
+    }
+    Ok(Some(manifest))
+}
+
+fn is_trusted(manifest: &IntegrityManifest) -> bool {
+    load_signing_key()
+        .ok()
+        .flatten()
+        .is_some_and(|key| hex::encode(key.verifying_key().to_bytes()) == manifest.public_key)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+fn file_digest(path: &Path) -> Result<FileDigest> {
+    Ok(FileDigest {
+        name: file_name(path),
+        size: std::fs::metadata(path)?.len(),
+        sha256: sha256_file(path)?,
+    })
+}
+
+/// `ok`, `missing`, `truncated` (shorter than when sealed) or `modified`
+fn file_status(path: &Path, expected: &FileDigest) -> &'static str {
+    match std::fs::metadata(path) {
+        Err(_) => "missing",
+        Ok(m) if m.len() < expected.size => "truncated",
+        Ok(m) if m.len() != expected.size => "modified",
+        Ok(_) => match sha256_file(path) {
+            Ok(hash) if hash == expected.sha256 => "ok",
+            Ok(_) => "modified",
+            Err(_) => "missing",
+        },
+    }
+}
+
+fn seal_blocking(video_path: &Path) -> Result<PathBuf> {
+    let (files, videos) = recording_files(video_path)?;
+    let digests = files
+        .iter()
+        .map(|path| file_digest(path))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut meta_path = video_path.to_path_buf();
+    meta_path.set_extension("meta.json");
+    let metadata: Value = std::fs::read_to_string(&meta_path)
+        .ok()
+        .and_then(|m| serde_json::from_str(&m).ok())
+        .unwrap_or(Value::Null);
+
+    let payload = ManifestPayload {
+        version: MANIFEST_VERSION,
+        session: SessionRecord {
+            session_id: video_path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            video_path: video_path.to_string_lossy().to_string(),
+            duration: metadata["duration"].as_f64().unwrap_or(0.0),
+            source: metadata["source"].as_str().map(str::to_string),
+            segments: videos,
+            recovered: metadata["recovered"].as_bool().unwrap_or(false),
+            sealed_at: chrono::Local::now().to_rfc3339(),
+        },
+        files: digests,
+    };
+
+    write_signed(video_path, payload, &signing_key()?)
+}
+
+fn write_signed(video_path: &Path, payload: ManifestPayload, key: &SigningKey) -> Result<PathBuf> {
+    let signature = key.sign(&serde_json::to_vec(&payload)?);
+    let manifest = IntegrityManifest {
+        payload,
+        public_key: hex::encode(key.verifying_key().to_bytes()),
+        signature: hex::encode(signature.to_bytes()),
+    };
+
+    let path = manifest_path(video_path);
+    std::fs::write(&path, serde_json::to_vec_pretty(&manifest)?)?;
+    tracing::info!(
+        "Sealed {:?} ({} files)",
+        video_path,
+        manifest.payload.files.len()
+    );
+    Ok(path)
+}
+
+/// Check a recording against its signed manifest. Reports every file as `ok`, `missing`,
+/// `truncated` (shorter than when sealed) or `modified`, and whether the signature holds
+/// and was made with this machine's key. Only all three together make it `valid`.
+pub async fn verify(video_path: &Path) -> Result<Value> {
+    let video_path = video_path.to_path_buf();
+    tokio::task::spawn_blocking(move || verify_blocking(&video_path)).await?
+}
+
+fn verify_blocking(video_path: &Path) -> Result<Value> {
+    let path = manifest_path(video_path);
+    let manifest: IntegrityManifest = serde_json::from_str(
+        &std::fs::read_to_string(&path)
+            .with_context(|| format!("No integrity manifest for {:?}", video_path))?,
+    )
+    .with_context(|| format!("Integrity manifest {:?} is corrupt", path))?;
+
+    let signature_valid = check_signature(&manifest).unwrap_or_else(|e| {
+        tracing::warn!("Invalid signature on {:?}: {}", path, e);
+        false
+    });
+    let trusted_key = is_trusted(&manifest);
+
+    let dir = video_path.parent().unwrap_or(Path::new(""));
+    let mut files_ok = true;
+    let files: Vec<Value> = manifest
+        .payload
+        .files
+        .iter()
+        .map(|expected| {
+            let status = file_status(&dir.join(&expected.name), expected);
+            files_ok &= status == "ok";
+            json!({ "name": expected.name, "status": status, "size": expected.size })
+        })
+        .collect();
+
+    let valid = signature_valid && trusted_key && files_ok;
+    if !valid {
+        tracing::warn!("Integrity check failed for {:?}", video_path);
+    }
+    Ok(json!({
+        "valid": valid,
+        "signature_valid": signature_valid,
+        "trusted_key": trusted_key,
+        "session": manifest.payload.session,
+        "files": files,
+    }))
+}
+
+fn check_signature(manifest: &IntegrityManifest) -> Result<bool> {
+    let public_key: [u8; 32] = hex::decode(&manifest.public_key)?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Public key has the wrong length"))?;
+    let signature: [u8; 64] = hex::decode(&manifest.signature)?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Signature has the wrong length"))?;
+    let key = VerifyingKey::from_bytes(&public_key)?;
+    Ok(key
+        .verify(
+            &serde_json::to_vec(&manifest.payload)?,
+            &Signature::from_bytes(&signature),
+        )
+        .is_ok())
+}
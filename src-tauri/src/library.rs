@@ -0,0 +1,572 @@
+use crate::analytics::chapters::Chapter;
+use crate::analytics::Marker;
+use crate::encryption;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use notify::event::ModifyKind;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+const VIDEO_EXTENSIONS: [&str; 4] = ["mkv", "mp4", "mov", "webm"];
+/// Files written next to a recording that show up in its entry
+const SIDECAR_SUFFIXES: [&str; 8] = [
+    ".meta.json",
+    ".manifest.json",
+    ".journal.json",
+    ".integrity.json",
+    ".chapters.vtt",
+    ".contact.png",
+    ".heatmap.png",
+    ".thumbs",
+];
+
+/// One recording in the library, summarized from its files and `.meta.json` sidecar
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LibraryEntry {
+    pub video_path: PathBuf,
+    pub meta_path: Option<PathBuf>,
+    pub recorded_at: String,
+    pub duration: f64,
+    pub source: Option<String>,
+    /// Video, segments and every sidecar file together
+    pub size_bytes: u64,
+    pub scene_changes: usize,
+    pub silence_seconds: f64,
+    pub idle_seconds: f64,
+    pub warnings: usize,
+    pub markers: Vec<Marker>,
+    pub chapters: Vec<Chapter>,
+    pub tags: Vec<String>,
+    /// The sidecar is encrypted, so only the duration and file facts are indexed
+    #[serde(default)]
+    pub encrypted: bool,
+    /// Newest modification time of the recording's files when it was indexed, a rescan
+    /// only re-reads the sidecar when this changes
+    modified: u64,
+}
+
+/// Filters for `search_recordings`, all optional and combined with AND
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct LibraryQuery {
+    /// Case-insensitive match on the file name, source, tags, marker labels and chapter titles
+    pub text: Option<String>,
+    /// RFC 3339 timestamp or `YYYY-MM-DD`, inclusive
+    pub from: Option<String>,
+    /// RFC 3339 timestamp or `YYYY-MM-DD`, inclusive
+    pub to: Option<String>,
+    /// Recordings must carry all of these
+    pub tags: Vec<String>,
+    pub source: Option<String>,
+    pub min_duration: Option<f64>,
+    pub max_duration: Option<f64>,
+    pub has_scene_changes: Option<bool>,
+}
+
+/// The parts of a sidecar the index needs, the timeline entries are skipped
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Sidecar {
+    duration: f64,
+    source: Option<String>,
+    events: Vec<SidecarEvent>,
+    silence_spans: Vec<Span>,
+    idle_spans: Vec<Span>,
+    warnings: Vec<IgnoredAny>,
+    markers: Vec<Marker>,
+    chapters: Vec<Chapter>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct SidecarEvent {
+    kind: String,
+}
+
+#[derive(Deserialize)]
+struct Span {
+    start: f64,
+    end: f64,
+}
+
+/// Files in the recordings folder that belong to one recording: `<stem>.*`, the
+/// `<stem>.thumbs` directory and `<stem>_partNNN.*` segments
+#[derive(Default)]
+struct FileGroup {
+    files: Vec<PathBuf>,
+    size_bytes: u64,
+    modified: u64,
+    oldest: Option<u64>,
+}
+
+/// Index of every recording in the recordings folder, persisted to `library.json` there.
+/// A watcher marks the index stale when files change behind the app's back, queries then
+/// [`Library::rescan`] first.
+pub struct Library {
+    dir: PathBuf,
+    index_path: PathBuf,
+    entries: BTreeMap<PathBuf, LibraryEntry>,
+    /// Set by the watcher, always set when the folder can't be watched
+    stale: Arc<AtomicBool>,
+    watcher: Option<RecommendedWatcher>,
+}
+
+impl Library {
+    pub fn load(dir: &Path) -> Result<Self> {
+        let index_path = dir.join("library.json");
+        let entries: Vec<LibraryEntry> = match std::fs::read_to_string(&index_path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                // Everything but tags can be rebuilt from the files themselves
+                tracing::warn!(
+                    "Rebuilding unreadable library index {:?}: {}",
+                    index_path,
+                    e
+                );
+                Vec::new()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let stale = Arc::new(AtomicBool::new(true));
+        let watcher = watch(dir, stale.clone())
+            .inspect_err(|e| {
+                tracing::warn!("Not watching {:?}, every query rescans it: {}", dir, e);
+            })
+            .ok();
+        let mut library = Self {
+            dir: dir.to_path_buf(),
+            index_path,
+            entries: entries
+                .into_iter()
+                .map(|e| (e.video_path.clone(), e))
+                .collect(),
+            stale,
+            watcher,
+        };
+        if let Err(e) = library.rescan() {
+            // The index stays stale, the next query tries again
+            tracing::warn!("Failed to scan {:?}: {}", dir, e);
+        }
+        tracing::info!("Library has {} recordings", library.entries.len());
+        Ok(library)
+    }
+
+    /// Empty and unwatched, for when the library can't be loaded. Every query rescans
+    /// `dir` and reports why it fails.
+    pub fn empty(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            index_path: dir.join("library.json"),
+            entries: BTreeMap::new(),
+            stale: Arc::new(AtomicBool::new(true)),
+            watcher: None,
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        let entries: Vec<&LibraryEntry> = self.entries.values().collect();
+        std::fs::write(&self.index_path, serde_json::to_vec_pretty(&entries)?)?;
+        Ok(())
+    }
+
+    /// Sync the index with the folder: index new and changed recordings, drop deleted
+    /// ones. Recordings still being written (or waiting for recovery) are left out.
+    pub fn rescan(&mut self) -> Result<()> {
+        // Cleared first, so changes made while scanning leave the index stale
+        self.stale.store(self.watcher.is_none(), Ordering::SeqCst);
+        let groups = match self.scan_groups() {
+            Ok(groups) => groups,
+            Err(e) => {
+                self.stale.store(true, Ordering::SeqCst);
+                return Err(e);
+            }
+        };
+        let mut entries = BTreeMap::new();
+        let mut changed = false;
+
+        for (stem, group) in &groups {
+            let Some(video_path) = self.video_path(stem, group) else {
+                continue;
+            };
+            let previous = self.entries.remove(&video_path);
+            let unchanged = previous
+                .as_ref()
+                .is_some_and(|p| p.modified == group.modified && p.size_bytes == group.size_bytes);
+            let entry = match previous {
+                Some(previous) if unchanged => previous,
+                previous => {
+                    changed = true;
+                    let mut entry = self.index(stem, video_path.clone(), group);
+                    entry.tags = previous.map(|p| p.tags).unwrap_or_default();
+                    entry
+                }
+            };
+            entries.insert(video_path, entry);
+        }
+
+        // Whatever was not matched again has been deleted
+        changed |= !self.entries.is_empty();
+        self.entries = entries;
+        if changed {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Every recording, newest first
+    pub fn list(&mut self) -> Result<Vec<LibraryEntry>> {
+        self.search(&LibraryQuery::default())
+    }
+
+    pub fn search(&mut self, query: &LibraryQuery) -> Result<Vec<LibraryEntry>> {
+        if self.stale.load(Ordering::SeqCst) {
+            self.rescan()?;
+        }
+        let from = query
+            .from
+            .as_deref()
+            .map(|t| parse_date(t, false))
+            .transpose()?;
+        let to = query
+            .to
+            .as_deref()
+            .map(|t| parse_date(t, true))
+            .transpose()?;
+        let text = query.text.as_ref().map(|t| t.to_lowercase());
+        let source = query.source.as_ref().map(|s| s.to_lowercase());
+
+        let mut matches: Vec<(DateTime<Local>, LibraryEntry)> = self
+            .entries
+            .values()
+            .filter(|e| query.min_duration.is_none_or(|min| e.duration >= min))
+            .filter(|e| query.max_duration.is_none_or(|max| e.duration <= max))
+            .filter(|e| {
+                query
+                    .has_scene_changes
+                    .is_none_or(|has| (e.scene_changes > 0) == has)
+            })
+            .filter(|e| query.tags.iter().all(|tag| e.tags.contains(tag)))
+            .filter(|e| {
+                source.as_ref().is_none_or(|source| {
+                    e.source
+                        .as_ref()
+                        .is_some_and(|s| s.to_lowercase().contains(source))
+                })
+            })
+            .filter(|e| text.as_ref().is_none_or(|text| matches_text(e, text)))
+            .filter_map(|e| {
+                let recorded_at = DateTime::parse_from_rfc3339(&e.recorded_at)
+                    .ok()?
+                    .with_timezone(&Local);
+                let in_range = from.is_none_or(|from| recorded_at >= from)
+                    && to.is_none_or(|to| recorded_at <= to);
+                in_range.then(|| (recorded_at, e.clone()))
+            })
+            .collect();
+
+        matches.sort_by_key(|(recorded_at, _)| std::cmp::Reverse(*recorded_at));
+        Ok(matches.into_iter().map(|(_, e)| e).collect())
+    }
+
+    pub fn set_tags(&mut self, video_path: &Path, tags: Vec<String>) -> Result<LibraryEntry> {
+        let entry = self
+            .entries
+            .get_mut(video_path)
+            .ok_or_else(|| anyhow::anyhow!("{:?} is not in the library", video_path))?;
+        let mut tags: Vec<String> = tags
+            .into_iter()
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+            .collect();
+        tags.sort();
+        tags.dedup();
+        entry.tags = tags;
+        let entry = entry.clone();
+        self.save()?;
+        Ok(entry)
+    }
+
+    /// Delete the recording's video, segments and sidecars from disk and the index
+    pub fn delete(&mut self, video_path: &Path) -> Result<LibraryEntry> {
+        let entry = self
+            .entries
+            .remove(video_path)
+            .ok_or_else(|| anyhow::anyhow!("{:?} is not in the library", video_path))?;
+
+        let stem = file_stem(video_path);
+        let groups = self.scan_groups()?;
+        for path in groups
+            .get(&stem)
+            .map(|g| g.files.as_slice())
+            .unwrap_or_default()
+        {
+            let result = if path.is_dir() {
+                std::fs::remove_dir_all(path)
+            } else {
+                std::fs::remove_file(path)
+            };
+            result.with_context(|| format!("Failed to delete {:?}", path))?;
+        }
+
+        self.save()?;
+        tracing::info!("Deleted recording {:?}", video_path);
+        Ok(entry)
+    }
+
+    /// Every file of a recording: video or segments, sidecars and the thumbnail folder
+    pub fn files(&self, video_path: &Path) -> Result<Vec<PathBuf>> {
+        if !self.entries.contains_key(video_path) {
+            return Err(anyhow::anyhow!("{:?} is not in the library", video_path));
+        }
+        let mut groups = self.scan_groups()?;
+        let mut files = groups
+            .remove(&file_stem(video_path))
+            .map(|g| g.files)
+            .unwrap_or_default();
+        files.sort();
+        Ok(files)
+    }
+
+    /// Group the folder's files by the recording they belong to
+    fn scan_groups(&self) -> Result<HashMap<String, FileGroup>> {
+        let mut groups: HashMap<String, FileGroup> = HashMap::new();
+        for dir_entry in std::fs::read_dir(&self.dir)? {
+            let dir_entry = dir_entry?;
+            let path = dir_entry.path();
+            if path == self.index_path {
+                continue;
+            }
+            let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            let metadata = dir_entry.metadata()?;
+            let (size, modified) = if metadata.is_dir() {
+                dir_stats(&path)
+            } else {
+                (metadata.len(), modified_secs(&metadata))
+            };
+
+            let group = groups.entry(owner_stem(name)).or_default();
+            group.files.push(path.clone());
+            group.size_bytes += size;
+            group.modified = group.modified.max(modified);
+            group.oldest = Some(group.oldest.map_or(modified, |o| o.min(modified)));
+        }
+        Ok(groups)
+    }
+
+    /// The group's main video, if it is a finished recording
+    fn video_path(&self, stem: &str, group: &FileGroup) -> Option<PathBuf> {
+        let has = |extension: &str| self.dir.join(format!("{}.{}", stem, extension));
+        if group.files.contains(&has("journal.json")) {
+            return None;
+        }
+        let video = VIDEO_EXTENSIONS
+            .iter()
+            .map(|ext| has(ext))
+            .find(|path| group.files.contains(path));
+        if group.files.contains(&has("meta.json")) {
+            // Segmented recordings have no single video file, the sidecar names it
+            return Some(video.unwrap_or_else(|| has("mkv")));
+        }
+        video
+    }
+
+    fn index(&self, stem: &str, video_path: PathBuf, group: &FileGroup) -> LibraryEntry {
+        let meta_path = self.dir.join(format!("{}.meta.json", stem));
+        let encrypted = encryption::is_encrypted(&meta_path);
+        let sidecar = if encrypted {
+            // Nothing from inside an encrypted recording goes into the plaintext index,
+            // the duration comes from the segment manifest
+            let manifest_path = self.dir.join(format!("{}.manifest.json", stem));
+            let duration = std::fs::read_to_string(manifest_path)
+                .ok()
+                .and_then(|m| serde_json::from_str::<serde_json::Value>(&m).ok())
+                .and_then(|m| m["duration"].as_f64())
+                .unwrap_or(0.0);
+            Some(Sidecar {
+                duration,
+                ..Sidecar::default()
+            })
+        } else if group.files.contains(&meta_path) {
+            match read_sidecar(&meta_path) {
+                Ok(sidecar) => Some(sidecar),
+                Err(e) => {
+                    tracing::warn!("Indexing {:?} without its sidecar: {}", video_path, e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let sidecar_found = sidecar.is_some();
+        let sidecar = sidecar.unwrap_or_default();
+
+        // Recordings are named `<kind>_YYYYmmdd_HHMMSS`, anything else falls back to
+        // the file times
+        let recorded_at = stem
+            .get(stem.len().saturating_sub(15)..)
+            .and_then(|t| NaiveDateTime::parse_from_str(t, "%Y%m%d_%H%M%S").ok())
+            .and_then(|t| Local.from_local_datetime(&t).earliest())
+            .or_else(|| {
+                let secs = group.oldest.unwrap_or(group.modified);
+                DateTime::from_timestamp(secs as i64, 0).map(|t| t.with_timezone(&Local))
+            })
+            .unwrap_or_else(Local::now);
+
+        let total = |spans: &[Span]| spans.iter().fold(0.0, |sum, s| sum + s.end - s.start);
+        LibraryEntry {
+            video_path,
+            meta_path: sidecar_found.then_some(meta_path),
+            recorded_at: recorded_at.to_rfc3339(),
+            duration: sidecar.duration,
+            source: sidecar.source,
+            size_bytes: group.size_bytes,
+            scene_changes: sidecar
+                .events
+                .iter()
+                .filter(|e| e.kind == "scene_change")
+                .count(),
+            silence_seconds: total(&sidecar.silence_spans),
+            idle_seconds: total(&sidecar.idle_spans),
+            warnings: sidecar.warnings.len(),
+            markers: sidecar.markers,
+            chapters: sidecar.chapters,
+            tags: Vec::new(),
+            encrypted,
+            modified: group.modified,
+        }
+    }
+}
+
+fn read_sidecar(path: &Path) -> Result<Sidecar> {
+    let file = std::fs::File::open(path)?;
+    serde_json::from_reader(std::io::BufReader::new(file))
+        .with_context(|| format!("Failed to parse {:?}", path))
+}
+
+fn matches_text(entry: &LibraryEntry, text: &str) -> bool {
+    let name = entry
+        .video_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    name.contains(text)
+        || entry
+            .source
+            .as_ref()
+            .is_some_and(|s| s.to_lowercase().contains(text))
+        || entry.tags.iter().any(|t| t.to_lowercase().contains(text))
+        || entry
+            .markers
+            .iter()
+            .any(|m| m.label.to_lowercase().contains(text))
+        || entry
+            .chapters
+            .iter()
+            .any(|c| c.title.to_lowercase().contains(text))
+}
+
+/// Stem of the recording a file belongs to: `recording_x` for `recording_x.meta.json`,
+/// `recording_x.thumbs` and `recording_x_part002.mkv`
+fn owner_stem(name: &str) -> String {
+    let stem = name.split('.').next().unwrap_or(name);
+    match stem.rsplit_once("_part") {
+        Some((owner, index)) if index.len() == 3 && index.bytes().all(|b| b.is_ascii_digit()) => {
+            owner.to_string()
+        }
+        _ => stem.to_string(),
+    }
+}
+
+fn file_stem(path: &Path) -> String {
+    owner_stem(
+        &path
+            .file_name()
+            .map(|n| n.to_string_lossy())
+            .unwrap_or_default(),
+    )
+}
+
+/// Mark `stale` whenever a recording or sidecar in `dir` is created, removed, written or
+/// renamed. Reads are ignored, the rescan itself would trigger them.
+fn watch(dir: &Path, stale: Arc<AtomicBool>) -> Result<RecommendedWatcher> {
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let relevant = match event {
+            Ok(event) => {
+                let changed = match event.kind {
+                    EventKind::Create(_) | EventKind::Remove(_) => true,
+                    EventKind::Modify(kind) => !matches!(kind, ModifyKind::Metadata(_)),
+                    _ => false,
+                };
+                event.need_rescan() || (changed && event.paths.iter().any(|p| is_recording_file(p)))
+            }
+            Err(_) => true,
+        };
+        if relevant {
+            stale.store(true, Ordering::SeqCst);
+        }
+    })?;
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    Ok(watcher)
+}
+
+/// Videos and the sidecars an entry is built from, not the index or temporary files
+fn is_recording_file(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    VIDEO_EXTENSIONS
+        .iter()
+        .map(|ext| format!(".{}", ext))
+        .chain(SIDECAR_SUFFIXES.iter().map(|s| s.to_string()))
+        .any(|suffix| name.ends_with(&suffix))
+}
+
+fn modified_secs(metadata: &std::fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Total size and newest modification time of the files in `dir`
+fn dir_stats(dir: &Path) -> (u64, u64) {
+    let Ok(read_dir) = std::fs::read_dir(dir) else {
+        return (0, 0);
+    };
+    read_dir
+        .filter_map(|e| e.ok()?.metadata().ok())
+        .fold((0, 0), |(size, modified), m| {
+            (size + m.len(), modified.max(modified_secs(&m)))
+        })
+}
+
+/// RFC 3339 timestamp, or a `YYYY-MM-DD` date taken as the start (or end) of that day
+fn parse_date(date: &str, end_of_day: bool) -> Result<DateTime<Local>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(date) {
+        return Ok(time.with_timezone(&Local));
+    }
+    let day = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .with_context(|| format!("Invalid date {:?}, expected YYYY-MM-DD or RFC 3339", date))?;
+    let time = if end_of_day {
+        day.and_hms_opt(23, 59, 59)
+    } else {
+        day.and_hms_opt(0, 0, 0)
+    }
+    .ok_or_else(|| anyhow::anyhow!("Invalid date {:?}", date))?;
+    Local
+        .from_local_datetime(&time)
+        .earliest()
+        .ok_or_else(|| anyhow::anyhow!("Invalid local time {:?}", date))
+}
@@ -2,19 +2,66 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod capture;
+mod chapters;
+mod disk;
 mod encoder;
+mod encryption;
 mod session;
 mod analytics;
+mod export;
+mod hooks;
+mod integrity;
+mod library;
 mod observability;
+mod offline;
+mod recovery;
+mod replay;
+mod retention;
+mod scheduler;
 mod system_metrics;
 
-use session::SessionManager;
+use analytics::query::TimelineQuery;
+use analytics::chapters::Chapter;
+use analytics::{AnalyticsConfig, Marker};
+use encoder::{BitratePreset, SegmentConfig};
+use encryption::KeySource;
+use hooks::{HookContext, HookStep};
+use library::{Library, LibraryEntry, LibraryQuery};
+use replay::ReplayConfig;
+use retention::{Janitor, RetentionPolicy};
+use scheduler::{Schedule, ScheduleRule, Scheduler};
+use session::{RecordingLimits, RecordingOptions, SessionManager};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use tauri::Emitter;
+use tauri_plugin_global_shortcut::{Code, GlobalShortcutExt, Modifiers, Shortcut, ShortcutState};
 use tokio::sync::Mutex;
 
 #[derive(Clone)]
 struct AppState {
     session_manager: Arc<Mutex<SessionManager>>,
+    /// Cancellation flags of running `analyze_file` jobs, keyed by video path
+    analysis_jobs: Arc<Mutex<HashMap<String, Arc<AtomicBool>>>>,
+    scheduler: Arc<Mutex<Scheduler>>,
+    /// `None` without a recordings folder
+    library: Option<Arc<Mutex<Library>>>,
+    /// `None` without a config directory
+    janitor: Option<Arc<Mutex<Janitor>>>,
+}
+
+impl AppState {
+    fn library(&self) -> Result<&Arc<Mutex<Library>>, String> {
+        self.library.as_ref().ok_or_else(|| {
+            "The recordings folder could not be determined, the library is unavailable".to_string()
+        })
+    }
+
+    fn janitor(&self) -> Result<&Arc<Mutex<Janitor>>, String> {
+        self.janitor.as_ref().ok_or_else(|| {
+            "The config directory could not be determined, retention is unavailable".to_string()
+        })
+    }
 }
 
 #[tauri::command]
@@ -36,15 +83,30 @@ async fn list_windows(state: tauri::State<'_, AppState>) -> Result<Vec<serde_jso
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn start_recording(
     monitor_id: Option<String>,
     window_id: Option<String>,
+    analytics_config: Option<AnalyticsConfig>,
+    bitrate_preset: Option<BitratePreset>,
+    segment_config: Option<SegmentConfig>,
+    limits: Option<RecordingLimits>,
+    encryption: Option<KeySource>,
     state: tauri::State<'_, AppState>,
     app: tauri::AppHandle,
 ) -> Result<(), String> {
+    let options = RecordingOptions {
+        monitor_id,
+        window_id,
+        analytics_config,
+        bitrate_preset,
+        segment_config,
+        limits: limits.unwrap_or_default(),
+        encryption,
+    };
     let mut manager = state.session_manager.lock().await;
     manager
-        .start_recording(monitor_id, window_id, app)
+        .start_recording(options, app)
         .await
         .map_err(|e| e.to_string())
 }
@@ -53,7 +115,7 @@ async fn start_recording(
 async fn stop_recording(
     state: tauri::State<'_, AppState>,
     app: tauri::AppHandle,
-) -> Result<String, String> {
+) -> Result<serde_json::Value, String> {
     let mut manager = state.session_manager.lock().await;
     manager.stop_recording(Some(app)).await.map_err(|e| e.to_string())
 }
@@ -71,9 +133,361 @@ async fn get_recording_status(state: tauri::State<'_, AppState>) -> Result<serde
 }
 
 #[tauri::command]
-async fn get_timeline_data(state: tauri::State<'_, AppState>) -> Result<Vec<serde_json::Value>, String> {
+async fn add_marker(
+    label: String,
+    kind: Option<String>,
+    state: tauri::State<'_, AppState>,
+    app: tauri::AppHandle,
+) -> Result<Marker, String> {
+    let manager = state.session_manager.lock().await;
+    manager
+        .add_marker(label, kind, Some(&app))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn set_replay_buffer(
+    config: Option<ReplayConfig>,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    let manager = state.session_manager.lock().await;
+    manager.set_replay_buffer(config).await;
+    Ok(())
+}
+
+#[tauri::command]
+async fn save_replay(state: tauri::State<'_, AppState>) -> Result<serde_json::Value, String> {
+    // Encoding takes a while, the session stays usable meanwhile
+    let snapshot = {
+        let manager = state.session_manager.lock().await;
+        manager.replay_snapshot().await.map_err(|e| e.to_string())?
+    };
+    observability::record_event("replay_saved", &[]);
+    replay::save(snapshot).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_timeline_data(
+    from: Option<f64>,
+    to: Option<f64>,
+    max_points: Option<usize>,
+    series: Option<Vec<String>>,
+    since: Option<u64>,
+    state: tauri::State<'_, AppState>,
+) -> Result<serde_json::Value, String> {
+    let manager = state.session_manager.lock().await;
+    manager
+        .get_timeline_data(TimelineQuery {
+            from,
+            to,
+            max_points,
+            series,
+            since,
+        })
+        .await
+}
+
+#[tauri::command]
+async fn get_motion_heatmap(
+    from: Option<f64>,
+    to: Option<f64>,
+    state: tauri::State<'_, AppState>,
+) -> Result<serde_json::Value, String> {
+    let manager = state.session_manager.lock().await;
+    manager.get_motion_heatmap(from, to).await
+}
+
+#[tauri::command]
+async fn analyze_file(
+    path: String,
+    output_dir: Option<String>,
+    analytics_config: Option<AnalyticsConfig>,
+    state: tauri::State<'_, AppState>,
+    app: tauri::AppHandle,
+) -> Result<String, String> {
+    let cancel = Arc::new(AtomicBool::new(false));
+    {
+        let mut jobs = state.analysis_jobs.lock().await;
+        if jobs.contains_key(&path) {
+            return Err(format!("{} is already being analyzed", path));
+        }
+        jobs.insert(path.clone(), cancel.clone());
+    }
+
+    let result = offline::analyze_file(
+        &std::path::PathBuf::from(&path),
+        output_dir.as_deref().map(std::path::Path::new),
+        analytics_config.unwrap_or_default(),
+        cancel,
+        |progress, time| {
+            let _ = app.emit(
+                "analysis-progress",
+                serde_json::json!({
+                    "path": path,
+                    "progress": progress,
+                    "time": time,
+                }),
+            );
+        },
+    )
+    .await;
+
+    state.analysis_jobs.lock().await.remove(&path);
+    result
+        .map(|p| p.to_string_lossy().to_string())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn cancel_analysis(path: String, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    let jobs = state.analysis_jobs.lock().await;
+    match jobs.get(&path) {
+        Some(cancel) => {
+            cancel.store(true, Ordering::Relaxed);
+            Ok(())
+        }
+        None => Err(format!("No analysis running for {}", path)),
+    }
+}
+
+#[tauri::command]
+async fn list_unfinished_recordings(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<serde_json::Value>, String> {
     let manager = state.session_manager.lock().await;
-    manager.get_timeline_data().await
+    let dir = session::recordings_dir().map_err(|e| e.to_string())?;
+    recovery::find_unfinished(&dir, manager.active_output_path()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn recover_recording(
+    journal_path: String,
+    key: Option<KeySource>,
+    state: tauri::State<'_, AppState>,
+) -> Result<serde_json::Value, String> {
+    let journal_path = std::path::PathBuf::from(journal_path);
+    {
+        let manager = state.session_manager.lock().await;
+        recovery::check_not_active(&journal_path, manager.active_output_path())
+            .map_err(|e| e.to_string())?;
+    }
+    recovery::recover(&journal_path, key)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn discard_unfinished_recording(
+    journal_path: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    let journal_path = std::path::PathBuf::from(journal_path);
+    let manager = state.session_manager.lock().await;
+    recovery::check_not_active(&journal_path, manager.active_output_path())
+        .map_err(|e| e.to_string())?;
+    recovery::discard(&journal_path).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn schedule_recording(
+    rule: ScheduleRule,
+    options: RecordingOptions,
+    state: tauri::State<'_, AppState>,
+) -> Result<Schedule, String> {
+    let mut scheduler = state.scheduler.lock().await;
+    scheduler.add(rule, options).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn list_schedules(state: tauri::State<'_, AppState>) -> Result<Vec<Schedule>, String> {
+    Ok(state.scheduler.lock().await.list())
+}
+
+#[tauri::command]
+async fn cancel_schedule(id: String, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    let mut scheduler = state.scheduler.lock().await;
+    scheduler.cancel(&id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn list_recordings(state: tauri::State<'_, AppState>) -> Result<Vec<LibraryEntry>, String> {
+    let mut library = state.library()?.lock().await;
+    library.list().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn search_recordings(
+    query: LibraryQuery,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<LibraryEntry>, String> {
+    let mut library = state.library()?.lock().await;
+    library.search(&query).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn set_recording_tags(
+    path: String,
+    tags: Vec<String>,
+    state: tauri::State<'_, AppState>,
+) -> Result<LibraryEntry, String> {
+    let mut library = state.library()?.lock().await;
+    library
+        .set_tags(&std::path::PathBuf::from(path), tags)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn delete_recording(
+    path: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<LibraryEntry, String> {
+    let mut library = state.library()?.lock().await;
+    library
+        .delete(&std::path::PathBuf::from(path))
+        .map_err(|e| e.to_string())
+}
+
+/// Decrypt an encrypted recording in place, it is resealed if it had an integrity manifest
+#[tauri::command]
+async fn decrypt_recording(
+    path: String,
+    key: KeySource,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<std::path::PathBuf>, String> {
+    let video_path = std::path::PathBuf::from(path);
+    let library = state.library()?.lock().await;
+    let files = library.files(&video_path).map_err(|e| e.to_string())?;
+    // Every file is rewritten, none may have been tampered with before
+    integrity::check_unchanged(&video_path, &files)
+        .await
+        .map_err(|e| e.to_string())?;
+    let decrypted = tokio::task::spawn_blocking(move || encryption::decrypt_recording(&files, key))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())?;
+    integrity::update(&video_path, &decrypted)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(decrypted)
+}
+
+/// Write decrypted copies of a recording into `destination`, leaving it encrypted
+#[tauri::command]
+async fn export_decrypted(
+    path: String,
+    key: KeySource,
+    destination: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<std::path::PathBuf>, String> {
+    let files = state
+        .library()?
+        .lock()
+        .await
+        .files(&std::path::PathBuf::from(path))
+        .map_err(|e| e.to_string())?;
+    tokio::task::spawn_blocking(move || {
+        encryption::export_decrypted(&files, key, std::path::Path::new(&destination))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_retention_policy(
+    state: tauri::State<'_, AppState>,
+) -> Result<RetentionPolicy, String> {
+    Ok(state.janitor()?.lock().await.policy())
+}
+
+#[tauri::command]
+async fn set_retention_policy(
+    policy: RetentionPolicy,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    let mut janitor = state.janitor()?.lock().await;
+    janitor.set_policy(policy).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn run_retention(
+    dry_run: Option<bool>,
+    state: tauri::State<'_, AppState>,
+) -> Result<serde_json::Value, String> {
+    let janitor = state.janitor()?.lock().await;
+    let mut library = state.library()?.lock().await;
+    janitor
+        .run(&mut library, dry_run.unwrap_or(true))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_retention_log(
+    limit: Option<usize>,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<serde_json::Value>, String> {
+    let janitor = state.janitor()?.lock().await;
+    janitor
+        .audit_log(limit.unwrap_or(100))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_post_processing_hooks() -> Result<Vec<HookStep>, String> {
+    hooks::load_steps().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn set_post_processing_hooks(steps: Vec<HookStep>) -> Result<(), String> {
+    hooks::save_steps(&steps).map_err(|e| e.to_string())
+}
+
+/// Run the configured hooks again on an existing recording, returning the step results
+#[tauri::command]
+async fn run_post_processing(
+    path: String,
+    app: tauri::AppHandle,
+) -> Result<serde_json::Value, String> {
+    let context = HookContext::from_recording(&std::path::PathBuf::from(path))
+        .map_err(|e| e.to_string())?;
+    let steps = hooks::load_steps().map_err(|e| e.to_string())?;
+    Ok(hooks::run(&context, &steps, Some(&app)).await)
+}
+
+/// Check a recording against its signed integrity manifest
+#[tauri::command]
+async fn verify_recording(path: String) -> Result<serde_json::Value, String> {
+    integrity::verify(&std::path::PathBuf::from(path))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_chapters(path: String) -> Result<Vec<Chapter>, String> {
+    chapters::load(&std::path::PathBuf::from(path)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn add_chapter(path: String, start: f64, title: String) -> Result<Vec<Chapter>, String> {
+    chapters::add_chapter(&std::path::PathBuf::from(path), start, title)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn rename_chapter(path: String, index: usize, title: String) -> Result<Vec<Chapter>, String> {
+    chapters::rename_chapter(&std::path::PathBuf::from(path), index, title)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn export_trimmed(path: String) -> Result<String, String> {
+    export::export_trimmed(&std::path::PathBuf::from(path))
+        .await
+        .map(|p| p.to_string_lossy().to_string())
+        .map_err(|e| e.to_string())
 }
 
 #[tokio::main]
@@ -81,14 +495,99 @@ async fn main() {
     // Initialize observability
     observability::init().expect("Failed to initialize observability");
 
+    // Headless subcommands run without the UI
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("analyze") {
+        std::process::exit(offline::run_cli(&args[2..]).await);
+    }
+
     // Initialize session manager
     let session_manager = Arc::new(Mutex::new(SessionManager::new().await));
 
-    let app_state = AppState { session_manager };
+    // Global hotkey that drops a marker while another window has focus
+    let marker_shortcut = Shortcut::new(Some(Modifiers::CONTROL | Modifiers::SHIFT), Code::KeyM);
+    let hotkey_session = session_manager.clone();
+
+    // Scheduled recordings survive restarts, and the same ticker enforces recording limits
+    let scheduler = Scheduler::load().unwrap_or_else(|e| {
+        tracing::warn!("Failed to load recording schedules, starting without any: {}", e);
+        Scheduler::empty()
+    });
+    let scheduler = Arc::new(Mutex::new(scheduler));
+    let scheduler_session = session_manager.clone();
+
+    let library = match session::recordings_dir() {
+        Ok(dir) => Some(Library::load(&dir).unwrap_or_else(|e| {
+            tracing::warn!("Failed to load recording library {:?}: {}", dir, e);
+            Library::empty(&dir)
+        })),
+        Err(e) => {
+            tracing::error!("No recordings folder, the library is unavailable: {}", e);
+            None
+        }
+    };
+    let library = library.map(|library| Arc::new(Mutex::new(library)));
+    let janitor = Janitor::load()
+        .or_else(|e| {
+            tracing::warn!("Failed to load retention policy, using the default: {}", e);
+            Janitor::with_default_policy()
+        })
+        .inspect_err(|e| tracing::error!("Retention is unavailable: {}", e))
+        .ok();
+    let janitor = janitor.map(|janitor| Arc::new(Mutex::new(janitor)));
+    let janitor_library = library.clone();
+    let janitor_ticker = janitor.clone();
+    let scheduler_ticker = scheduler.clone();
+
+    let app_state = AppState {
+        session_manager,
+        analysis_jobs: Arc::new(Mutex::new(HashMap::new())),
+        scheduler,
+        library,
+        janitor,
+    };
 
     tauri::Builder::default()
         .plugin(tauri_plugin_shell::init())
+        .plugin(
+            tauri_plugin_global_shortcut::Builder::new()
+                .with_handler(move |app, shortcut, event| {
+                    if shortcut != &marker_shortcut || event.state() != ShortcutState::Pressed {
+                        return;
+                    }
+                    let session = hotkey_session.clone();
+                    let app = app.clone();
+                    tauri::async_runtime::spawn(async move {
+                        let manager = session.lock().await;
+                        let marker = manager
+                            .add_marker("Marker".to_string(), None, Some(&app))
+                            .await;
+                        if let Err(e) = marker {
+                            tracing::warn!("Marker hotkey ignored: {}", e);
+                        }
+                    });
+                })
+                .build(),
+        )
         .manage(app_state)
+        .setup(move |app| {
+            // Another app may already own the hotkey, markers then only come from the UI
+            if let Err(e) = app.global_shortcut().register(marker_shortcut) {
+                tracing::warn!("Marker hotkey unavailable: {}", e);
+                let _ = app.emit(
+                    "shortcut-unavailable",
+                    serde_json::json!({
+                        "shortcut": marker_shortcut.to_string(),
+                        "error": e.to_string(),
+                    }),
+                );
+            }
+            scheduler::spawn(scheduler_ticker, scheduler_session, app.handle().clone());
+            if let (Some(janitor), Some(library)) = (janitor_ticker, janitor_library) {
+                retention::spawn(janitor, library, app.handle().clone());
+            }
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             list_monitors,
             list_windows,
@@ -96,7 +595,37 @@ async fn main() {
             stop_recording,
             pause_recording,
             get_recording_status,
+            add_marker,
+            set_replay_buffer,
+            save_replay,
             get_timeline_data,
+            get_motion_heatmap,
+            analyze_file,
+            cancel_analysis,
+            list_unfinished_recordings,
+            recover_recording,
+            discard_unfinished_recording,
+            schedule_recording,
+            list_schedules,
+            cancel_schedule,
+            list_recordings,
+            search_recordings,
+            set_recording_tags,
+            delete_recording,
+            decrypt_recording,
+            export_decrypted,
+            get_retention_policy,
+            set_retention_policy,
+            run_retention,
+            get_retention_log,
+            get_post_processing_hooks,
+            set_post_processing_hooks,
+            run_post_processing,
+            verify_recording,
+            get_chapters,
+            add_chapter,
+            rename_chapter,
+            export_trimmed,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
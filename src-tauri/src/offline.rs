@@ -0,0 +1,323 @@
+use crate::analytics::{AnalyticsConfig, AnalyticsPipeline};
+use crate::capture::Frame;
+use crate::chapters;
+use crate::encoder::BitratePreset;
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::io::AsyncReadExt;
+use tokio::process::{Child, ChildStdout, Command};
+
+/// Audio is decoded at this rate for its levels, plenty for an RMS
+const AUDIO_SAMPLE_RATE: u32 = 8000;
+
+/// Stream properties read from ffprobe
+struct VideoInfo {
+    width: u32,
+    height: u32,
+    fps: f64,
+    duration: f64,
+    bitrate_kbps: Option<u32>,
+    has_audio: bool,
+}
+
+/// The file's audio as mono samples from a second `ffmpeg`, read alongside the frames
+struct AudioLevels {
+    _child: Child,
+    stdout: ChildStdout,
+    /// Samples read so far
+    position: u64,
+    buffer: Vec<u8>,
+}
+
+impl AudioLevels {
+    fn spawn(video_path: &Path) -> Result<Self> {
+        let mut child = Command::new("ffmpeg")
+            .args(["-v", "error", "-i"])
+            .arg(video_path)
+            .args(["-vn", "-ac", "1", "-ar"])
+            .arg(AUDIO_SAMPLE_RATE.to_string())
+            .args(["-f", "s16le", "-"])
+            .stdout(Stdio::piped())
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .context("Failed to run ffmpeg")?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("ffmpeg produced no audio stream"))?;
+        Ok(Self {
+            _child: child,
+            stdout,
+            position: 0,
+            buffer: Vec::new(),
+        })
+    }
+
+    /// RMS level (0-1) of the samples up to `until` seconds, `None` once the audio ended
+    async fn level_until(&mut self, until: f64) -> Result<Option<f64>> {
+        let end = ((until * AUDIO_SAMPLE_RATE as f64).round() as u64).max(self.position + 1);
+        self.buffer.resize((end - self.position) as usize * 2, 0);
+        match self.stdout.read_exact(&mut self.buffer).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        self.position = end;
+
+        let samples = self.buffer.len() / 2;
+        let sum: f64 = self
+            .buffer
+            .chunks_exact(2)
+            .map(|s| (i16::from_le_bytes([s[0], s[1]]) as f64 / 32768.0).powi(2))
+            .sum();
+        Ok(Some((sum / samples as f64).sqrt()))
+    }
+}
+
+/// Run the live analytics pipeline over an existing video file and write the same
+/// `.meta.json` sidecar a recording gets, plus heatmap, thumbnails and chapters. They go
+/// into `output_dir` when set, next to the video otherwise. Frames are decoded by an
+/// `ffmpeg` subprocess, the audio levels for silence detection by another one.
+///
+/// `on_progress` receives the fraction done (0-1) and the analyzed timestamp. Setting
+/// `cancel` stops decoding and discards the partial results.
+pub async fn analyze_file(
+    video_path: &Path,
+    output_dir: Option<&Path>,
+    config: AnalyticsConfig,
+    cancel: Arc<AtomicBool>,
+    mut on_progress: impl FnMut(f64, f64),
+) -> Result<PathBuf> {
+    let info = probe(video_path).await?;
+    tracing::info!(
+        "Analyzing {:?}: {}x{} at {:.2} fps, {:.1}s",
+        video_path,
+        info.width,
+        info.height,
+        info.fps,
+        info.duration
+    );
+
+    let bitrate_kbps = info
+        .bitrate_kbps
+        .unwrap_or_else(|| BitratePreset::default().kbps());
+    // Outputs are named after the video, in the output folder if there is one
+    let output_base = match (output_dir, video_path.file_name()) {
+        (Some(dir), Some(name)) => {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create output folder {:?}", dir))?;
+            dir.join(name)
+        }
+        _ => video_path.to_path_buf(),
+    };
+    let mut analytics = AnalyticsPipeline::new(config, bitrate_kbps, &output_base, None)
+        .context("Failed to create analytics pipeline")?;
+    analytics.set_source("file".to_string());
+    analytics.set_video_path(video_path.to_path_buf());
+
+    // Frames must come out at the size ffprobe reported, so no rotation from metadata
+    let mut child = Command::new("ffmpeg")
+        .args(["-v", "error", "-noautorotate", "-i"])
+        .arg(video_path)
+        .args(["-f", "rawvideo", "-pix_fmt", "rgb24", "-"])
+        .stdout(Stdio::piped())
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to run ffmpeg")?;
+    let mut stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow::anyhow!("ffmpeg produced no output stream"))?;
+
+    let mut audio = if info.has_audio {
+        Some(AudioLevels::spawn(video_path)?)
+    } else {
+        None
+    };
+
+    // Frames carry wall-clock time like live capture, counted from when the file was
+    // recorded: its creation time, or the end of the recording where that isn't kept
+    let metadata = std::fs::metadata(video_path)?;
+    let started_at = metadata
+        .created()
+        .ok()
+        .or_else(|| {
+            let duration = std::time::Duration::from_secs_f64(info.duration.max(0.0));
+            metadata.modified().ok()?.checked_sub(duration)
+        })
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_millis() as u64);
+
+    let frame_size = info.width as usize * info.height as usize * 3;
+    let mut buffer = vec![0u8; frame_size];
+    let mut frame_index = 0u64;
+    let mut last_progress = -1.0;
+
+    loop {
+        if cancel.load(Ordering::Relaxed) {
+            let _ = child.kill().await;
+            analytics.discard();
+            return Err(anyhow::anyhow!("Analysis cancelled"));
+        }
+
+        match stdout.read_exact(&mut buffer).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        }
+
+        let time = frame_index as f64 / info.fps;
+        let frame = Frame {
+            data: std::mem::take(&mut buffer),
+            width: info.width,
+            height: info.height,
+            timestamp: started_at + (time * 1000.0) as u64,
+        };
+        let audio_level = match audio.as_mut() {
+            Some(audio) => audio.level_until(time + 1.0 / info.fps).await?,
+            None => None,
+        };
+        analytics.process_frame(&frame, time, audio_level).await;
+        buffer = frame.data;
+        frame_index += 1;
+
+        // Report at most once per percent
+        let progress = if info.duration > 0.0 {
+            (time / info.duration).min(1.0)
+        } else {
+            0.0
+        };
+        if progress - last_progress >= 0.01 {
+            on_progress(progress, time);
+            last_progress = progress;
+        }
+    }
+
+    let status = child.wait().await?;
+    if !status.success() {
+        analytics.discard();
+        return Err(anyhow::anyhow!("ffmpeg exited with {}", status));
+    }
+
+    analytics.save_metadata(&output_base).await?;
+    if let Err(e) = chapters::write_tracks(&output_base, &analytics.chapters(), None).await {
+        tracing::warn!("Failed to write chapters: {}", e);
+    }
+    on_progress(1.0, info.duration);
+
+    let mut meta_path = output_base;
+    meta_path.set_extension("meta.json");
+    tracing::info!("Analyzed {} frames into {:?}", frame_index, meta_path);
+    Ok(meta_path)
+}
+
+async fn probe(video_path: &Path) -> Result<VideoInfo> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-show_entries",
+            "stream=codec_type,width,height,avg_frame_rate:format=duration,bit_rate",
+            "-of",
+            "json",
+        ])
+        .arg(video_path)
+        .output()
+        .await
+        .context("Failed to run ffprobe")?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "ffprobe failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let probe: Value = serde_json::from_slice(&output.stdout)?;
+    let streams = probe["streams"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let find_stream = |kind: &str| streams.iter().find(|s| s["codec_type"] == kind);
+    let stream = find_stream("video").unwrap_or(&Value::Null);
+    let width = stream["width"]
+        .as_u64()
+        .ok_or_else(|| anyhow::anyhow!("No video stream in {:?}", video_path))?;
+    let height = stream["height"].as_u64().unwrap_or(0);
+
+    // avg_frame_rate is a fraction like "30000/1001"
+    let fps = stream["avg_frame_rate"]
+        .as_str()
+        .and_then(|rate| {
+            let (num, den) = rate.split_once('/')?;
+            let (num, den) = (num.parse::<f64>().ok()?, den.parse::<f64>().ok()?);
+            (den > 0.0 && num > 0.0).then(|| num / den)
+        })
+        .unwrap_or(30.0);
+
+    let parse = |v: &Value| v.as_str().and_then(|s| s.parse::<f64>().ok());
+    Ok(VideoInfo {
+        width: width as u32,
+        height: height as u32,
+        fps,
+        duration: parse(&probe["format"]["duration"]).unwrap_or(0.0),
+        bitrate_kbps: parse(&probe["format"]["bit_rate"]).map(|b| (b / 1000.0) as u32),
+        has_audio: find_stream("audio").is_some(),
+    })
+}
+
+/// `screen-recorder analyze [--output-dir <dir>] <video>...`: analyze files from the
+/// command line, Ctrl+C cancels
+pub async fn run_cli(args: &[String]) -> i32 {
+    let mut output_dir = None;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output-dir" => output_dir = args.next().map(PathBuf::from),
+            _ => paths.push(arg),
+        }
+    }
+    if paths.is_empty() {
+        eprintln!("usage: screen-recorder analyze [--output-dir <dir>] <video>...");
+        return 2;
+    }
+
+    let cancel = Arc::new(AtomicBool::new(false));
+    let cancel_on_signal = cancel.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            cancel_on_signal.store(true, Ordering::Relaxed);
+        }
+    });
+
+    let mut exit_code = 0;
+    for path in paths {
+        let result = analyze_file(
+            Path::new(path),
+            output_dir.as_deref(),
+            AnalyticsConfig::default(),
+            cancel.clone(),
+            |progress, _| eprint!("\r{}: {:>3.0}%", path, progress * 100.0),
+        )
+        .await;
+        eprintln!();
+
+        match result {
+            Ok(meta_path) => println!("{}", meta_path.display()),
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                exit_code = 1;
+            }
+        }
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+    }
+    exit_code
+}
@@ -0,0 +1,479 @@
+use crate::analytics::chapters::{self, ChapterDetector};
+use crate::analytics::idle::IdleDetector;
+use crate::analytics::{store, AnalyticsConfig, Marker, SilenceDetector};
+use crate::capture::CaptureSource;
+use crate::encoder::{BitratePreset, SegmentConfig};
+use crate::encryption::{self, InputFile, KeySource, Keyring, OutputFile, RecordingKey};
+use crate::integrity;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+/// Written next to a recording while it is in progress and removed once it is finalized.
+/// A journal found on startup means the app died mid-recording.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Journal {
+    pub video_path: PathBuf,
+    pub started_at: String,
+    pub updated_at: String,
+    pub monitor_id: Option<String>,
+    pub window_id: Option<String>,
+    pub bitrate_preset: BitratePreset,
+    pub segment_config: SegmentConfig,
+    pub analytics_config: AnalyticsConfig,
+    /// Encoder manifest at the last checkpoint
+    pub manifest: Value,
+    /// Timeline entries flushed to the `.timeline.jsonl` store at the last checkpoint
+    pub timeline_entries: u64,
+    /// Session seconds recorded at the last checkpoint
+    pub checkpoint_time: f64,
+    /// Segments, timeline and sidecars are encrypted, recovering needs the key
+    #[serde(default)]
+    pub encrypted: bool,
+}
+
+impl Journal {
+    pub fn new(
+        video_path: PathBuf,
+        monitor_id: Option<String>,
+        window_id: Option<String>,
+        bitrate_preset: BitratePreset,
+        segment_config: SegmentConfig,
+        analytics_config: AnalyticsConfig,
+        encrypted: bool,
+    ) -> Self {
+        let now = chrono::Local::now().to_rfc3339();
+        Self {
+            video_path,
+            started_at: now.clone(),
+            updated_at: now,
+            monitor_id,
+            window_id,
+            bitrate_preset,
+            segment_config,
+            analytics_config,
+            manifest: Value::Null,
+            timeline_entries: 0,
+            checkpoint_time: 0.0,
+            encrypted,
+        }
+    }
+
+    pub fn path(&self) -> PathBuf {
+        journal_path(&self.video_path)
+    }
+
+    /// Replace the journal on disk. Written to a temporary file first so a crash during
+    /// the write leaves the previous checkpoint intact.
+    pub fn write(&self) -> Result<()> {
+        let path = self.path();
+        let mut tmp_path = path.clone();
+        tmp_path.set_extension("json.tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    pub fn checkpoint(&mut self, manifest: Value, timeline_entries: u64, time: f64) -> Result<()> {
+        self.manifest = manifest;
+        self.timeline_entries = timeline_entries;
+        self.checkpoint_time = time;
+        self.updated_at = chrono::Local::now().to_rfc3339();
+        self.write()
+    }
+
+    /// The recording was finalized, nothing left to recover
+    pub fn remove(&self) {
+        let _ = std::fs::remove_file(self.path());
+    }
+}
+
+fn journal_path(video_path: &Path) -> PathBuf {
+    let mut path = video_path.to_path_buf();
+    path.set_extension("journal.json");
+    path
+}
+
+fn timeline_store_path(video_path: &Path) -> PathBuf {
+    let mut path = video_path.to_path_buf();
+    path.set_extension("timeline.jsonl");
+    path
+}
+
+fn read_journal(path: &Path) -> Result<Journal> {
+    let journal = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read journal {:?}", path))?;
+    Ok(serde_json::from_str(&journal)?)
+}
+
+/// Journals of recordings in `dir` that never finished, `active` (the recording in
+/// progress, if any) excluded
+pub fn find_unfinished(dir: &Path, active: Option<&Path>) -> Result<Vec<Value>> {
+    let mut unfinished = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_journal = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.ends_with(".journal.json"));
+        if !is_journal {
+            continue;
+        }
+
+        let journal = match read_journal(&path) {
+            Ok(journal) => journal,
+            Err(e) => {
+                tracing::warn!("Skipping unreadable journal {:?}: {}", path, e);
+                continue;
+            }
+        };
+        if active.is_some_and(|a| a == journal.video_path) {
+            continue;
+        }
+
+        unfinished.push(json!({
+            "journal_path": path,
+            "video_path": journal.video_path,
+            "started_at": journal.started_at,
+            "updated_at": journal.updated_at,
+            "duration": journal.checkpoint_time,
+            "segments": journal.manifest["segments"].as_array().map(Vec::len).unwrap_or(0),
+            "timeline_entries": journal.timeline_entries,
+            "encrypted": journal.encrypted,
+        }));
+    }
+    Ok(unfinished)
+}
+
+/// Fail for the journal of `active`, the recording in progress, whose files are still
+/// being written
+pub fn check_not_active(journal_path: &Path, active: Option<&Path>) -> Result<()> {
+    let journal = read_journal(journal_path)?;
+    if active.is_some_and(|a| a == journal.video_path) {
+        return Err(anyhow::anyhow!(
+            "{:?} is still being recorded",
+            journal.video_path
+        ));
+    }
+    Ok(())
+}
+
+/// Forget an unfinished recording, its video segments are left where they are
+pub fn discard(journal_path: &Path) -> Result<()> {
+    let journal = read_journal(journal_path)?;
+    let _ = std::fs::remove_file(timeline_store_path(&journal.video_path));
+    std::fs::remove_file(journal_path)?;
+    tracing::info!("Discarded unfinished recording {:?}", journal.video_path);
+    Ok(())
+}
+
+/// Salvage an unfinished recording: remux every segment that made it to disk so it gets a
+/// proper index and trailer, then rebuild meta.json from the checkpointed timeline.
+/// Encrypted recordings need `key`; their segments are not remuxed but rewritten up to
+/// the last complete chunk, and their sidecars are written encrypted again. Returns the
+/// recovered segment manifest.
+pub async fn recover(journal_path: &Path, key: Option<KeySource>) -> Result<Value> {
+    let journal = read_journal(journal_path)?;
+    tracing::info!("Recovering {:?}", journal.video_path);
+
+    let mut keyring = match (journal.encrypted, key) {
+        (false, _) => None,
+        (true, Some(source)) => Some(Keyring::new(source)),
+        (true, None) => {
+            return Err(anyhow::anyhow!(
+                "Recording is encrypted, a passphrase or keyfile is needed to recover it"
+            ))
+        }
+    };
+    let key = match keyring.as_mut() {
+        Some(keyring) => {
+            // Every file of a recording shares the key, any one that survived unlocks it
+            let store_path = timeline_store_path(&journal.video_path);
+            let unlock_from = std::iter::once(store_path)
+                .chain(segment_paths(&journal.manifest))
+                .find(|path| path.exists())
+                .ok_or_else(|| anyhow::anyhow!("Nothing of the recording was written"))?;
+            Some(keyring.key_for(&unlock_from)?)
+        }
+        None => None,
+    };
+
+    let mut segments = Vec::new();
+    for segment in journal.manifest["segments"]
+        .as_array()
+        .into_iter()
+        .flatten()
+    {
+        let Some(path) = segment["path"].as_str().map(PathBuf::from) else {
+            continue;
+        };
+        if !path.exists() {
+            tracing::warn!("Segment {:?} was never written, skipping", path);
+            continue;
+        }
+        match key {
+            // ffmpeg can't read encrypted segments, they are only closed properly
+            Some(key) => finish_encrypted(&path, key)
+                .with_context(|| format!("Failed to recover segment {:?}", path))?,
+            None => {
+                if let Err(e) = remux(&path).await {
+                    // Keep the segment as-is, players can often still read a truncated file
+                    tracing::warn!("Failed to remux {:?}: {}", path, e);
+                }
+            }
+        }
+        segments.push(segment.clone());
+    }
+
+    let mut manifest = journal.manifest.clone();
+    manifest["segments"] = json!(segments);
+    manifest["recovered"] = json!(true);
+    if let Some(path) = manifest["manifest_path"].as_str() {
+        std::fs::write(path, serde_json::to_vec_pretty(&manifest)?)?;
+    }
+
+    regenerate_metadata(&journal, &manifest, key).await?;
+    if let Err(e) = integrity::seal(&journal.video_path).await {
+        tracing::warn!("Failed to seal {:?}: {}", journal.video_path, e);
+    }
+
+    let _ = std::fs::remove_file(timeline_store_path(&journal.video_path));
+    std::fs::remove_file(journal_path)?;
+    tracing::info!(
+        "Recovered {:?} with {} segments",
+        journal.video_path,
+        segments.len()
+    );
+    Ok(manifest)
+}
+
+/// Stream copy the file into a fresh container, which rewrites the index and trailer a
+/// crashed muxer never got to write
+async fn remux(path: &Path) -> Result<()> {
+    let mut remuxed_path = path.to_path_buf();
+    remuxed_path.set_extension("recovered.mkv");
+    let status = Command::new("ffmpeg")
+        .args(["-y", "-v", "error", "-i"])
+        .arg(path)
+        .args(["-map", "0", "-c", "copy"])
+        .arg(&remuxed_path)
+        .status()
+        .await
+        .context("Failed to run ffmpeg")?;
+    if !status.success() {
+        let _ = std::fs::remove_file(&remuxed_path);
+        return Err(anyhow::anyhow!("ffmpeg exited with {}", status));
+    }
+    std::fs::rename(&remuxed_path, path)?;
+    Ok(())
+}
+
+/// Rewrite an encrypted segment the crash cut off, up to its last complete chunk and with
+/// a proper last chunk, so it no longer reads as truncated
+fn finish_encrypted(path: &Path, key: &RecordingKey) -> Result<()> {
+    // Not even the header made it to disk
+    if std::fs::metadata(path)?.len() == 0 {
+        return encryption::write_file(path, &[], Some(key));
+    }
+    let mut finished_path = path.to_path_buf();
+    finished_path.set_extension("recovered.tmp");
+    let result = (|| -> Result<()> {
+        let mut input = InputFile::open_partial(path, Some(key))?;
+        let mut output = OutputFile::create(&finished_path, Some(key))?;
+        std::io::copy(&mut input, &mut output)?;
+        output.finish()
+    })();
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&finished_path);
+        return Err(e);
+    }
+    std::fs::rename(&finished_path, path)?;
+    Ok(())
+}
+
+/// Segment files listed in an encoder manifest
+fn segment_paths(manifest: &Value) -> impl Iterator<Item = PathBuf> + '_ {
+    manifest["segments"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|segment| segment["path"].as_str().map(PathBuf::from))
+}
+
+/// Rebuild the sidecar from the timeline entries flushed before the crash. Spans come
+/// from the recorded motion and audio levels through the pipeline's detectors, markers
+/// and chapters from the events; a torn last line is skipped. Without a timeline the sidecar only gets the duration of the salvaged segments.
+async fn regenerate_metadata(
+    journal: &Journal,
+    manifest: &Value,
+    key: Option<&RecordingKey>,
+) -> Result<()> {
+    let store_path = timeline_store_path(&journal.video_path);
+    let has_timeline = store_path.exists();
+    if !has_timeline {
+        tracing::warn!(
+            "Timeline {:?} is missing, recovering {:?} without analytics",
+            store_path,
+            journal.video_path
+        );
+    }
+    let entries = || -> Result<_> {
+        if !has_timeline {
+            return Ok(None);
+        }
+        let file = InputFile::open_partial(&store_path, key)
+            .with_context(|| format!("Failed to open timeline {:?}", store_path))?;
+        Ok(Some(
+            BufReader::new(file)
+                .lines()
+                .map_while(|line| line.ok())
+                .filter(|line| serde_json::from_str::<Value>(line).is_ok()),
+        ))
+    };
+
+    let mut duration: f64 = manifest["segments"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|s| Some(s["start"].as_f64()? + s["duration"].as_f64()?))
+        .fold(0.0, f64::max);
+    let mut has_audio = false;
+    let mut events = Vec::new();
+    let mut markers = Vec::new();
+    // Spans follow the same thresholds and minimum durations as during the recording
+    let config = &journal.analytics_config;
+    let mut silence = SilenceDetector::new(config.silence_threshold, config.silence_min_duration);
+    let mut idle = IdleDetector::new(config.idle_threshold, config.idle_min_duration);
+    let mut chapter_detector = ChapterDetector::new(journal.analytics_config.chapters.clone());
+
+    for line in entries()?.into_iter().flatten() {
+        let entry: Value = serde_json::from_str(&line)?;
+        let time = entry["time"].as_f64().unwrap_or(duration);
+        duration = duration.max(time);
+        if let Some(level) = entry["audioLevel"].as_f64() {
+            has_audio = true;
+            silence.update(time, level);
+        }
+        if let Some(motion) = entry["motion"].as_f64() {
+            idle.update(time, motion);
+        }
+
+        for event in entry["events"].as_array().into_iter().flatten() {
+            let mut event = event.clone();
+            event["time"] = json!(time);
+            match event["kind"].as_str() {
+                Some("scene_change") => chapter_detector.scene_change(time),
+                Some("marker") => markers.push(Marker {
+                    time: event["data"]["markerTime"].as_f64().unwrap_or(time),
+                    label: event["data"]["label"].as_str().unwrap_or("").to_string(),
+                    kind: event["data"]["kind"]
+                        .as_str()
+                        .unwrap_or("bookmark")
+                        .to_string(),
+                }),
+                _ => {}
+            }
+            events.push(event);
+        }
+    }
+
+    let mut chapter_list = chapter_detector.chapters(duration);
+    for marker in &markers {
+        chapters::split(&mut chapter_list, marker.time, marker.label.clone());
+    }
+
+    let source = match (&journal.monitor_id, &journal.window_id) {
+        (Some(id), _) => Some(CaptureSource::Monitor(id.clone()).label()),
+        (None, Some(id)) => Some(CaptureSource::Window(id.clone()).label()),
+        (None, None) => None,
+    };
+    let metadata = json!({
+        "video_path": journal.video_path.to_string_lossy(),
+        "duration": duration,
+        "source": source,
+        "recovered": true,
+        "events": events,
+        "audio": has_audio,
+        "silence_spans": silence.spans(duration),
+        "idle_spans": idle.spans(duration),
+        "chapters": chapter_list,
+        "markers": markers,
+        "segments": manifest["segments"],
+    });
+
+    let mut meta_path = journal.video_path.clone();
+    meta_path.set_extension("meta.json");
+    store::write_sidecar(
+        &meta_path,
+        &metadata,
+        entries()?.into_iter().flatten().map(Ok),
+        key,
+    )?;
+
+    if let Err(e) = crate::chapters::write_tracks(&journal.video_path, &chapter_list, key).await {
+        tracing::warn!("Failed to write chapters: {}", e);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[tokio::test]
+    async fn recovers_encrypted_recording() {
+        let dir = std::env::temp_dir().join(format!("recovery_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let keyfile = dir.join("recording.key");
+        std::fs::write(&keyfile, [7u8; 32]).unwrap();
+        let source = KeySource::Keyfile { path: keyfile };
+        let key = RecordingKey::new(&source).unwrap();
+
+        // Segment and timeline flushed at the last checkpoint, then the crash
+        let video_path = dir.join("recording.mkv");
+        let segment_path = dir.join("recording_001.mkv");
+        let mut segment = OutputFile::create(&segment_path, Some(&key)).unwrap();
+        segment.write_all(b"encoded video").unwrap();
+        segment.flush().unwrap();
+        drop(segment);
+        let mut timeline =
+            OutputFile::create(&timeline_store_path(&video_path), Some(&key)).unwrap();
+        writeln!(timeline, "{}", json!({ "time": 1.0, "events": [] })).unwrap();
+        timeline.flush().unwrap();
+        drop(timeline);
+
+        let mut journal = Journal::new(
+            video_path.clone(),
+            None,
+            None,
+            BitratePreset::default(),
+            SegmentConfig::default(),
+            AnalyticsConfig::default(),
+            true,
+        );
+        journal.manifest = json!({
+            "segments": [{ "path": segment_path, "start": 0.0, "duration": 2.0 }],
+        });
+        journal.write().unwrap();
+
+        recover(&journal.path(), Some(source.clone()))
+            .await
+            .unwrap();
+
+        let mut meta_path = video_path.clone();
+        meta_path.set_extension("meta.json");
+        let decrypted =
+            encryption::decrypt_recording(&[segment_path.clone(), meta_path.clone()], source)
+                .unwrap();
+        assert_eq!(decrypted.len(), 2);
+        assert_eq!(std::fs::read(&segment_path).unwrap(), b"encoded video");
+        let metadata: Value = serde_json::from_slice(&std::fs::read(&meta_path).unwrap()).unwrap();
+        assert_eq!(metadata["duration"], 2.0);
+        assert_eq!(metadata["recovered"], true);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
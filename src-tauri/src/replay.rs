@@ -0,0 +1,323 @@
+use crate::analytics::{store, thumbnails};
+use crate::capture::Frame;
+use crate::encryption::{OutputFile, RecordingKey};
+use anyhow::{Context, Result};
+use image::codecs::jpeg::JpegEncoder;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+use tokio::sync::Mutex;
+
+/// Frames still being compressed before the buffer starts skipping, so a slow machine
+/// drops replay frames instead of stalling capture
+const MAX_PENDING_FRAMES: usize = 2;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct ReplayConfig {
+    /// Length of the window kept for `save_replay`
+    pub duration_seconds: f64,
+    /// Memory cap for the compressed frames, the window shrinks if it is hit
+    pub max_memory_mb: usize,
+    /// Frames wider than this are scaled down before compression
+    pub max_width: u32,
+    pub jpeg_quality: u8,
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        Self {
+            duration_seconds: 30.0,
+            max_memory_mb: 256,
+            max_width: 1280,
+            jpeg_quality: 80,
+        }
+    }
+}
+
+/// A JPEG compressed frame at `time` session seconds. Shared so a save can snapshot the
+/// ring without copying frame data or blocking new frames.
+#[derive(Clone)]
+pub struct ReplayFrame {
+    time: f64,
+    jpeg: Arc<Vec<u8>>,
+}
+
+impl ReplayFrame {
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+}
+
+/// Bounded ring of the most recent frames, evicted by age and by total size
+pub struct ReplayBuffer {
+    config: ReplayConfig,
+    frames: VecDeque<ReplayFrame>,
+    bytes: usize,
+    pending: Arc<AtomicUsize>,
+    /// Bumped by `reset`, compressions started before it are dropped
+    generation: u64,
+}
+
+impl ReplayBuffer {
+    pub fn new(config: ReplayConfig) -> Self {
+        Self {
+            config,
+            frames: VecDeque::new(),
+            bytes: 0,
+            pending: Arc::new(AtomicUsize::new(0)),
+            generation: 0,
+        }
+    }
+
+    /// Drop every frame, for a new recording whose session time starts over
+    pub fn reset(&mut self) {
+        self.frames.clear();
+        self.bytes = 0;
+        self.generation += 1;
+    }
+
+    /// Frames finish compressing out of order, each is inserted by its time
+    fn push(&mut self, frame: ReplayFrame) {
+        self.bytes += frame.jpeg.len();
+        let newest = self
+            .frames
+            .back()
+            .map_or(frame.time, |f| f.time.max(frame.time));
+        let oldest = newest - self.config.duration_seconds;
+        let index = self
+            .frames
+            .iter()
+            .rposition(|f| f.time <= frame.time)
+            .map_or(0, |i| i + 1);
+        self.frames.insert(index, frame);
+
+        let max_bytes = self.config.max_memory_mb * 1024 * 1024;
+        while let Some(front) = self.frames.front() {
+            if front.time >= oldest && self.bytes <= max_bytes {
+                break;
+            }
+            self.bytes -= front.jpeg.len();
+            self.frames.pop_front();
+        }
+    }
+
+    /// Current contents, cheap to take since frame data is shared
+    pub fn snapshot(&self) -> Vec<ReplayFrame> {
+        self.frames.iter().cloned().collect()
+    }
+}
+
+/// Compress `frame` into the buffer on a blocking thread, if replay is enabled and the
+/// previous frames are done
+pub async fn feed(replay: &Arc<Mutex<Option<ReplayBuffer>>>, frame: &Frame, time: f64) {
+    let (config, pending, generation) = match replay.lock().await.as_ref() {
+        Some(buffer) => (
+            buffer.config.clone(),
+            buffer.pending.clone(),
+            buffer.generation,
+        ),
+        None => return,
+    };
+    if pending.fetch_add(1, Ordering::AcqRel) >= MAX_PENDING_FRAMES {
+        pending.fetch_sub(1, Ordering::AcqRel);
+        return;
+    }
+
+    let frame = Frame {
+        data: frame.data.clone(),
+        width: frame.width,
+        height: frame.height,
+        timestamp: frame.timestamp,
+    };
+    let replay = replay.clone();
+    tokio::spawn(async move {
+        let jpeg = tokio::task::spawn_blocking(move || compress(&frame, &config)).await;
+        pending.fetch_sub(1, Ordering::AcqRel);
+        match jpeg {
+            Ok(Ok(jpeg)) => {
+                let mut replay = replay.lock().await;
+                if let Some(buffer) = replay.as_mut().filter(|b| b.generation == generation) {
+                    buffer.push(ReplayFrame {
+                        time,
+                        jpeg: Arc::new(jpeg),
+                    });
+                }
+            }
+            Ok(Err(e)) => tracing::warn!("Failed to compress replay frame: {}", e),
+            Err(e) => tracing::warn!("Replay compression task failed: {}", e),
+        }
+    });
+}
+
+fn compress(frame: &Frame, config: &ReplayConfig) -> Result<Vec<u8>> {
+    let image = thumbnails::downscale(frame, config.max_width.min(frame.width))
+        .ok_or_else(|| anyhow::anyhow!("Empty frame"))?;
+    let mut jpeg = Vec::new();
+    JpegEncoder::new_with_quality(&mut jpeg, config.jpeg_quality).encode_image(&image)?;
+    Ok(jpeg)
+}
+
+/// Capture rate from the median gap between frames, so gaps in the buffer don't lower it
+fn frame_rate(frames: &[ReplayFrame]) -> f64 {
+    let mut gaps: Vec<f64> = frames
+        .windows(2)
+        .map(|pair| pair[1].time - pair[0].time)
+        .filter(|gap| *gap > 0.0)
+        .collect();
+    gaps.sort_by(f64::total_cmp);
+    gaps.get(gaps.len() / 2).map_or(30.0, |gap| 1.0 / gap)
+}
+
+/// Everything a save needs from the session, taken under its lock so the encode can run
+/// without holding it
+pub struct ReplaySnapshot {
+    pub frames: Vec<ReplayFrame>,
+    /// Session timeline entries covering the frames
+    pub timeline: Vec<Value>,
+    pub output_path: PathBuf,
+    /// Recording the replay was cut from
+    pub source_path: Option<PathBuf>,
+    /// The session's key, the replay is encrypted with it too
+    pub key: Option<Arc<RecordingKey>>,
+}
+
+/// Encode the snapshot into its output path and write its slice of the session timeline
+/// as the replay's meta.json, with times rebased to the start of the replay. Requires an
+/// `ffmpeg` binary on the PATH. With a key, ffmpeg's output is piped through an
+/// [`OutputFile`] so the clip never reaches the disk in plaintext.
+pub async fn save(snapshot: ReplaySnapshot) -> Result<Value> {
+    let ReplaySnapshot {
+        frames,
+        timeline,
+        output_path,
+        source_path,
+        key,
+    } = snapshot;
+    let (Some(first), Some(last)) = (frames.first(), frames.last()) else {
+        return Err(anyhow::anyhow!("Replay buffer is empty"));
+    };
+    let (start, end) = (first.time, last.time);
+    let fps = frame_rate(&frames);
+
+    let mut command = Command::new("ffmpeg");
+    command
+        .args([
+            "-y",
+            "-v",
+            "error",
+            "-f",
+            "image2pipe",
+            "-c:v",
+            "mjpeg",
+            "-framerate",
+        ])
+        .arg(format!("{:.3}", fps))
+        .args(["-i", "-", "-c:v", "libx264", "-preset", "veryfast"])
+        .args(["-pix_fmt", "yuv420p"])
+        .stdin(Stdio::piped())
+        .kill_on_drop(true);
+    match &key {
+        Some(_) => command
+            .args(["-f", "matroska", "pipe:1"])
+            .stdout(Stdio::piped()),
+        None => command.arg(&output_path),
+    };
+    let mut child = command.spawn().context("Failed to run ffmpeg")?;
+    let writer = match child.stdout.take() {
+        Some(mut stdout) => {
+            let mut file = OutputFile::create(&output_path, key.as_deref())?;
+            Some(tokio::spawn(async move {
+                let mut buffer = vec![0u8; 64 * 1024];
+                loop {
+                    let len = stdout.read(&mut buffer).await?;
+                    if len == 0 {
+                        break;
+                    }
+                    file.write_all(&buffer[..len])?;
+                }
+                file.finish()
+            }))
+        }
+        None => None,
+    };
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow::anyhow!("ffmpeg has no input stream"))?;
+    // Frames are missing where the screen was idle or compression fell behind, hold the
+    // previous one over those gaps so the clip keeps the session's timing
+    for (i, frame) in frames.iter().enumerate() {
+        let repeats = frames.get(i + 1).map_or(1, |next| {
+            ((next.time - frame.time) * fps).round().max(1.0) as usize
+        });
+        for _ in 0..repeats {
+            stdin.write_all(&frame.jpeg).await?;
+        }
+    }
+    drop(stdin);
+
+    let written = match writer {
+        Some(writer) => writer.await?,
+        None => Ok(()),
+    };
+    let status = child.wait().await?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("ffmpeg exited with {}", status));
+    }
+    written.with_context(|| format!("Failed to write {:?}", output_path))?;
+
+    let mut events = Vec::new();
+    let entries: Vec<Value> = timeline
+        .into_iter()
+        .map(|mut entry| {
+            let session_time = entry["time"].as_f64().unwrap_or(start);
+            entry["time"] = json!(session_time - start);
+            entry["sessionTime"] = json!(session_time);
+            for event in entry["events"].as_array().into_iter().flatten() {
+                let mut event = event.clone();
+                event["time"] = json!(session_time - start);
+                events.push(event);
+            }
+            entry
+        })
+        .collect();
+
+    let metadata = json!({
+        "video_path": output_path.to_string_lossy(),
+        "source_video_path": source_path.as_deref().map(|p| p.to_string_lossy()),
+        "replay_start": start,
+        "duration": end - start,
+        "frames": frames.len(),
+        "events": events,
+    });
+    let mut meta_path = output_path.to_path_buf();
+    meta_path.set_extension("meta.json");
+    store::write_sidecar(
+        &meta_path,
+        &metadata,
+        entries.iter().map(|e| Ok(e.to_string())),
+        key.as_deref(),
+    )?;
+
+    tracing::info!(
+        "Saved {:.1}s replay ({} frames) to {:?}",
+        end - start,
+        frames.len(),
+        output_path
+    );
+    Ok(json!({
+        "video_path": output_path,
+        "meta_path": meta_path,
+        "start": start,
+        "duration": end - start,
+        "frames": frames.len(),
+    }))
+}
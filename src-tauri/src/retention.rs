@@ -0,0 +1,440 @@
+use crate::library::{Library, LibraryEntry};
+use crate::session;
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter};
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+
+/// Which recordings the janitor deletes. Every limit is optional; recordings kept by
+/// `keep_marked` or `keep_tagged` are never deleted, even if that leaves a limit exceeded.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    /// Run the janitor in the background, otherwise it only runs on request
+    pub enabled: bool,
+    pub interval_minutes: u64,
+    /// Keep only the newest N recordings
+    pub keep_last: Option<usize>,
+    /// Delete the oldest recordings until the library fits
+    pub max_total_size_mb: Option<u64>,
+    pub max_age_days: Option<f64>,
+    /// Never delete recordings with markers
+    pub keep_marked: bool,
+    /// Never delete recordings with tags
+    pub keep_tagged: bool,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_minutes: 60,
+            keep_last: None,
+            max_total_size_mb: None,
+            max_age_days: None,
+            keep_marked: true,
+            keep_tagged: true,
+        }
+    }
+}
+
+impl RetentionPolicy {
+    fn validate(&self) -> Result<()> {
+        if self.interval_minutes == 0 {
+            return Err(anyhow::anyhow!(
+                "Retention interval must be at least a minute"
+            ));
+        }
+        if self.max_age_days.is_some_and(|days| days <= 0.0) {
+            return Err(anyhow::anyhow!("max_age_days must be positive"));
+        }
+        Ok(())
+    }
+
+    fn protects(&self, entry: &LibraryEntry) -> bool {
+        (self.keep_marked && !entry.markers.is_empty())
+            || (self.keep_tagged && !entry.tags.is_empty())
+    }
+
+    /// Recordings this policy deletes out of `entries` (newest first), with the rule that
+    /// caught each one
+    fn plan(&self, entries: &[LibraryEntry], now: DateTime<Local>) -> Vec<(usize, &'static str)> {
+        let mut doomed: Vec<Option<&'static str>> = vec![None; entries.len()];
+        let deletable = |i: usize, doomed: &[Option<&'static str>]| {
+            doomed[i].is_none() && !self.protects(&entries[i])
+        };
+
+        if let Some(max_age) = self.max_age_days {
+            let cutoff = now - chrono::Duration::seconds((max_age * 86400.0) as i64);
+            for (i, entry) in entries.iter().enumerate() {
+                let too_old =
+                    DateTime::parse_from_rfc3339(&entry.recorded_at).is_ok_and(|t| t < cutoff);
+                if too_old && deletable(i, &doomed) {
+                    doomed[i] = Some("max_age");
+                }
+            }
+        }
+
+        if let Some(keep_last) = self.keep_last {
+            let surviving: Vec<usize> = (0..entries.len())
+                .filter(|&i| doomed[i].is_none())
+                .collect();
+            for &i in surviving.iter().skip(keep_last) {
+                if deletable(i, &doomed) {
+                    doomed[i] = Some("keep_last");
+                }
+            }
+        }
+
+        if let Some(max_mb) = self.max_total_size_mb {
+            let max_bytes = max_mb * 1024 * 1024;
+            let mut total: u64 = (0..entries.len())
+                .filter(|&i| doomed[i].is_none())
+                .map(|i| entries[i].size_bytes)
+                .sum();
+            for i in (0..entries.len()).rev() {
+                if total <= max_bytes {
+                    break;
+                }
+                if deletable(i, &doomed) {
+                    doomed[i] = Some("max_total_size");
+                    total -= entries[i].size_bytes;
+                }
+            }
+        }
+
+        doomed
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, reason)| Some((i, reason?)))
+            .collect()
+    }
+}
+
+/// Applies the retention policy to the library. The policy is persisted to
+/// `retention.json` and every deletion is appended to `retention_audit.jsonl`, both in
+/// the config directory.
+pub struct Janitor {
+    path: PathBuf,
+    audit_path: PathBuf,
+    policy: RetentionPolicy,
+}
+
+impl Janitor {
+    pub fn load() -> Result<Self> {
+        let dir = session::config_dir()?;
+        let path = dir.join("retention.json");
+        let policy = match std::fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                tracing::warn!(
+                    "Unreadable retention policy {:?}, using the default: {}",
+                    path,
+                    e
+                );
+                RetentionPolicy::default()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => RetentionPolicy::default(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path,
+            audit_path: dir.join("retention_audit.jsonl"),
+            policy,
+        })
+    }
+
+    /// The default policy, which deletes nothing, for when `load` fails
+    pub fn with_default_policy() -> Result<Self> {
+        let dir = session::config_dir()?;
+        Ok(Self {
+            path: dir.join("retention.json"),
+            audit_path: dir.join("retention_audit.jsonl"),
+            policy: RetentionPolicy::default(),
+        })
+    }
+
+    pub fn policy(&self) -> RetentionPolicy {
+        self.policy.clone()
+    }
+
+    pub fn set_policy(&mut self, policy: RetentionPolicy) -> Result<()> {
+        policy.validate()?;
+        std::fs::write(&self.path, serde_json::to_vec_pretty(&policy)?)?;
+        self.policy = policy;
+        Ok(())
+    }
+
+    /// Apply the policy, or with `dry_run` only report what it would delete
+    pub fn run(&self, library: &mut Library, dry_run: bool) -> Result<Value> {
+        let entries = library.list()?;
+        let now = Local::now();
+        let plan = self.policy.plan(&entries, now);
+
+        let mut deleted = Vec::new();
+        let mut errors = Vec::new();
+        let mut freed_bytes = 0;
+        for (i, reason) in plan {
+            let entry = &entries[i];
+            if !dry_run {
+                if let Err(e) = library.delete(&entry.video_path) {
+                    tracing::warn!("Retention failed to delete {:?}: {}", entry.video_path, e);
+                    errors.push(json!({ "video_path": entry.video_path, "error": e.to_string() }));
+                    continue;
+                }
+                self.audit(entry, reason, now);
+            }
+            freed_bytes += entry.size_bytes;
+            deleted.push(json!({
+                "video_path": entry.video_path,
+                "recorded_at": entry.recorded_at,
+                "size_bytes": entry.size_bytes,
+                "reason": reason,
+            }));
+        }
+
+        if !dry_run && !deleted.is_empty() {
+            tracing::info!(
+                "Retention deleted {} recordings, freed {} MB",
+                deleted.len(),
+                freed_bytes / 1024 / 1024
+            );
+        }
+        Ok(json!({
+            "dry_run": dry_run,
+            "ran_at": now.to_rfc3339(),
+            "policy": self.policy,
+            "deleted": deleted,
+            "freed_bytes": freed_bytes,
+            "kept": entries.len() - deleted.len(),
+            "errors": errors,
+        }))
+    }
+
+    fn audit(&self, entry: &LibraryEntry, reason: &str, now: DateTime<Local>) {
+        let line = json!({
+            "time": now.to_rfc3339(),
+            "video_path": entry.video_path,
+            "recorded_at": entry.recorded_at,
+            "size_bytes": entry.size_bytes,
+            "reason": reason,
+        });
+        let result = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.audit_path)
+            .and_then(|mut file| writeln!(file, "{}", line));
+        if let Err(e) = result {
+            tracing::warn!("Failed to write retention audit log: {}", e);
+        }
+    }
+
+    /// The most recent `limit` deletions, newest first
+    pub fn audit_log(&self, limit: usize) -> Result<Vec<Value>> {
+        let file = match std::fs::File::open(&self.audit_path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut lines: Vec<Value> = BufReader::new(file)
+            .lines()
+            .map_while(|line| line.ok())
+            .filter_map(|line| serde_json::from_str(&line).ok())
+            .collect();
+        lines.reverse();
+        lines.truncate(limit);
+        Ok(lines)
+    }
+}
+
+/// Run the janitor every `interval_minutes` while the policy is enabled, starting right
+/// away. Emits `retention-report` whenever something was deleted.
+pub fn spawn(janitor: Arc<Mutex<Janitor>>, library: Arc<Mutex<Library>>, app: AppHandle) {
+    tokio::spawn(async move {
+        let mut last_run: Option<Instant> = None;
+        let mut interval = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+
+            let janitor = janitor.lock().await;
+            let policy = janitor.policy();
+            let due = last_run
+                .is_none_or(|t| t.elapsed() >= Duration::from_secs(policy.interval_minutes * 60));
+            if !policy.enabled || !due {
+                continue;
+            }
+            last_run = Some(Instant::now());
+
+            let mut library = library.lock().await;
+            match janitor.run(&mut library, false) {
+                Ok(report) => {
+                    if report["deleted"].as_array().is_some_and(|d| !d.is_empty()) {
+                        let _ = app.emit("retention-report", report);
+                    }
+                }
+                Err(e) => tracing::error!("Retention run failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analytics::Marker;
+
+    const MB: u64 = 1024 * 1024;
+
+    /// A recording made `days_old` days before `now`
+    fn entry(now: DateTime<Local>, days_old: i64, size_mb: u64) -> LibraryEntry {
+        serde_json::from_value(json!({
+            "video_path": format!("recording_{}.mkv", days_old),
+            "meta_path": null,
+            "recorded_at": (now - chrono::Duration::days(days_old)).to_rfc3339(),
+            "duration": 60.0,
+            "source": null,
+            "size_bytes": size_mb * MB,
+            "scene_changes": 0,
+            "silence_seconds": 0.0,
+            "idle_seconds": 0.0,
+            "warnings": 0,
+            "markers": [],
+            "chapters": [],
+            "tags": [],
+            "modified": 0,
+        }))
+        .unwrap()
+    }
+
+    fn tagged(mut entry: LibraryEntry) -> LibraryEntry {
+        entry.tags = vec!["keep".to_string()];
+        entry
+    }
+
+    fn marked(mut entry: LibraryEntry) -> LibraryEntry {
+        entry.markers = vec![Marker {
+            time: 1.0,
+            label: "Marker".to_string(),
+            kind: "bookmark".to_string(),
+        }];
+        entry
+    }
+
+    fn policy() -> RetentionPolicy {
+        RetentionPolicy {
+            enabled: true,
+            ..RetentionPolicy::default()
+        }
+    }
+
+    #[test]
+    fn keep_last_skips_protected_recordings() {
+        let now = Local::now();
+        let entries = [
+            entry(now, 0, 1),
+            entry(now, 1, 1),
+            tagged(entry(now, 2, 1)),
+            entry(now, 3, 1),
+        ];
+        let plan = RetentionPolicy {
+            keep_last: Some(1),
+            ..policy()
+        }
+        .plan(&entries, now);
+        assert_eq!(plan, vec![(1, "keep_last"), (3, "keep_last")]);
+    }
+
+    #[test]
+    fn max_total_size_deletes_oldest_unprotected_first() {
+        let now = Local::now();
+        let entries = [
+            entry(now, 0, 10),
+            marked(entry(now, 1, 10)),
+            entry(now, 2, 10),
+            entry(now, 3, 10),
+        ];
+        let plan = RetentionPolicy {
+            max_total_size_mb: Some(25),
+            ..policy()
+        }
+        .plan(&entries, now);
+        assert_eq!(plan, vec![(2, "max_total_size"), (3, "max_total_size")]);
+    }
+
+    #[test]
+    fn max_total_size_leaves_protected_recordings_over_the_limit() {
+        let now = Local::now();
+        let entries = [
+            tagged(entry(now, 0, 30)),
+            entry(now, 1, 10),
+            marked(entry(now, 2, 30)),
+        ];
+        let plan = RetentionPolicy {
+            max_total_size_mb: Some(20),
+            ..policy()
+        }
+        .plan(&entries, now);
+        assert_eq!(plan, vec![(1, "max_total_size")]);
+    }
+
+    #[test]
+    fn max_age_keeps_protected_recordings() {
+        let now = Local::now();
+        let entries = [
+            entry(now, 1, 1),
+            tagged(entry(now, 10, 1)),
+            entry(now, 11, 1),
+            marked(entry(now, 12, 1)),
+        ];
+        let plan = RetentionPolicy {
+            max_age_days: Some(7.0),
+            ..policy()
+        }
+        .plan(&entries, now);
+        assert_eq!(plan, vec![(2, "max_age")]);
+    }
+
+    #[test]
+    fn unprotected_when_keep_flags_are_off() {
+        let now = Local::now();
+        let entries = [tagged(entry(now, 10, 1)), marked(entry(now, 11, 1))];
+        let plan = RetentionPolicy {
+            max_age_days: Some(7.0),
+            keep_marked: false,
+            keep_tagged: false,
+            ..policy()
+        }
+        .plan(&entries, now);
+        assert_eq!(plan, vec![(0, "max_age"), (1, "max_age")]);
+    }
+
+    #[test]
+    fn limits_only_count_recordings_earlier_rules_kept() {
+        let now = Local::now();
+        let entries = [
+            entry(now, 0, 10),
+            entry(now, 1, 10),
+            entry(now, 2, 10),
+            entry(now, 30, 10),
+            tagged(entry(now, 31, 10)),
+        ];
+        // max_age takes 3, keep_last then keeps 0 and 1 of the rest and takes 2, which
+        // leaves 30 MB with the protected 4 and max_total_size takes 1
+        let plan = RetentionPolicy {
+            max_age_days: Some(7.0),
+            keep_last: Some(2),
+            max_total_size_mb: Some(20),
+            ..policy()
+        }
+        .plan(&entries, now);
+        assert_eq!(
+            plan,
+            vec![(1, "max_total_size"), (2, "keep_last"), (3, "max_age")]
+        );
+    }
+}
@@ -0,0 +1,379 @@
+use crate::encryption::KeySource;
+use crate::session::{self, RecordingOptions, SessionManager};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, Duration, Local, Timelike};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter};
+use tokio::sync::Mutex;
+
+/// When a scheduled recording starts
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ScheduleRule {
+    /// Once, at an RFC 3339 timestamp
+    Once { at: String },
+    /// Whenever a 5-field cron expression (minute hour day-of-month month day-of-week,
+    /// local time) matches. Fields accept `*`, numbers, `a-b` ranges, `,` lists and `/n`
+    /// steps. As in cron, a day matching either day field is enough when both are set.
+    Cron { expression: String },
+}
+
+impl ScheduleRule {
+    /// First start time strictly after `after`, `None` once a one-off schedule has passed
+    fn next_after(&self, after: DateTime<Local>) -> Result<Option<DateTime<Local>>> {
+        match self {
+            ScheduleRule::Once { at } => {
+                let at = parse_time(at)?;
+                Ok((at > after).then_some(at))
+            }
+            ScheduleRule::Cron { expression } => {
+                Ok(CronExpression::parse(expression)?.next_after(after))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Schedule {
+    pub id: String,
+    pub rule: ScheduleRule,
+    pub options: RecordingOptions,
+    pub next_run: Option<String>,
+    pub created_at: String,
+}
+
+/// A due schedule that did not start a recording
+#[derive(Clone, Debug, Serialize)]
+pub struct MissedSchedule {
+    pub id: String,
+    pub run: Option<String>,
+    /// `app_closed`, `late` or `recording_in_progress`
+    pub reason: &'static str,
+}
+
+/// Due schedules this far behind their start time are reported as missed instead of
+/// started, e.g. after the machine slept through them
+const MAX_START_DELAY_SECONDS: i64 = 60;
+
+/// Scheduled recordings, persisted to `schedules.json` in the config directory
+pub struct Scheduler {
+    /// `None` without a config directory, in which case schedules can't be saved
+    path: Option<PathBuf>,
+    schedules: Vec<Schedule>,
+    /// Not yet reported by the ticker
+    missed: Vec<MissedSchedule>,
+}
+
+impl Scheduler {
+    pub fn load() -> Result<Self> {
+        let path = session::config_dir()?.join("schedules.json");
+
+        let schedules = match std::fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .with_context(|| format!("Failed to parse schedules {:?}", path))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let mut scheduler = Self {
+            path: Some(path),
+            schedules,
+            missed: Vec::new(),
+        };
+
+        // One-off schedules missed while the app was closed are dropped, rules roll forward
+        let now = Local::now();
+        let mut missed = Vec::new();
+        scheduler
+            .schedules
+            .retain_mut(|schedule| match schedule.rule.next_after(now) {
+                Ok(Some(next)) => {
+                    schedule.next_run = Some(next.to_rfc3339());
+                    true
+                }
+                _ => {
+                    missed.push(schedule.missed("app_closed"));
+                    false
+                }
+            });
+        for missed in &missed {
+            tracing::warn!(
+                "Schedule {} was due at {:?} while the app was closed",
+                missed.id,
+                missed.run
+            );
+        }
+        scheduler.missed = missed;
+        scheduler.save()?;
+        tracing::info!("Loaded {} recording schedules", scheduler.schedules.len());
+        Ok(scheduler)
+    }
+
+    /// No schedules, for when `schedules.json` can't be loaded. Adding one replaces the file.
+    pub fn empty() -> Self {
+        Self {
+            path: session::config_dir()
+                .map(|dir| dir.join("schedules.json"))
+                .ok(),
+            schedules: Vec::new(),
+            missed: Vec::new(),
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = self
+            .path
+            .as_ref()
+            .context("The config directory could not be determined, schedules can't be saved")?;
+        std::fs::write(path, serde_json::to_vec_pretty(&self.schedules)?)?;
+        Ok(())
+    }
+
+    pub fn add(&mut self, rule: ScheduleRule, options: RecordingOptions) -> Result<Schedule> {
+        options.limits.validate()?;
+        // Schedules are saved as plain JSON, a passphrase has no place there
+        if matches!(options.encryption, Some(KeySource::Passphrase { .. })) {
+            return Err(anyhow::anyhow!(
+                "Scheduled recordings can only be encrypted with a keyfile"
+            ));
+        }
+        let now = Local::now();
+        let next = rule
+            .next_after(now)?
+            .ok_or_else(|| anyhow::anyhow!("Schedule never runs, the start time has passed"))?;
+
+        let schedule = Schedule {
+            id: format!("schedule_{}", now.timestamp_millis()),
+            rule,
+            options,
+            next_run: Some(next.to_rfc3339()),
+            created_at: now.to_rfc3339(),
+        };
+        self.schedules.push(schedule.clone());
+        self.save()?;
+        tracing::info!("Scheduled recording {} for {}", schedule.id, next);
+        Ok(schedule)
+    }
+
+    pub fn list(&self) -> Vec<Schedule> {
+        self.schedules.clone()
+    }
+
+    pub fn cancel(&mut self, id: &str) -> Result<()> {
+        let before = self.schedules.len();
+        self.schedules.retain(|s| s.id != id);
+        if self.schedules.len() == before {
+            return Err(anyhow::anyhow!("No schedule with id {}", id));
+        }
+        self.save()
+    }
+
+    /// Schedules whose start time has come. Each is advanced to its next run, one-off
+    /// schedules are removed. Runs too far in the past are recorded as missed instead.
+    fn take_due(&mut self, now: DateTime<Local>) -> Vec<Schedule> {
+        let mut due = Vec::new();
+        let mut advanced = false;
+        for schedule in &mut self.schedules {
+            let Some(run) = schedule
+                .next_run
+                .as_deref()
+                .and_then(|t| parse_time(t).ok())
+                .filter(|run| *run <= now)
+            else {
+                continue;
+            };
+            if now - run > Duration::seconds(MAX_START_DELAY_SECONDS) {
+                tracing::warn!(
+                    "Schedule {} missed its start at {}, {}s ago",
+                    schedule.id,
+                    run,
+                    (now - run).num_seconds()
+                );
+                self.missed.push(schedule.missed("late"));
+            } else {
+                due.push(schedule.clone());
+            }
+            schedule.next_run = schedule
+                .rule
+                .next_after(now)
+                .ok()
+                .flatten()
+                .map(|next| next.to_rfc3339());
+            advanced = true;
+        }
+        if advanced {
+            self.schedules.retain(|s| s.next_run.is_some());
+            if let Err(e) = self.save() {
+                tracing::warn!("Failed to save schedules: {}", e);
+            }
+        }
+        due
+    }
+
+    /// Missed schedules not reported yet
+    fn take_missed(&mut self) -> Vec<MissedSchedule> {
+        std::mem::take(&mut self.missed)
+    }
+}
+
+impl Schedule {
+    fn missed(&self, reason: &'static str) -> MissedSchedule {
+        MissedSchedule {
+            id: self.id.clone(),
+            run: self.next_run.clone(),
+            reason,
+        }
+    }
+}
+
+/// Once a second, start due scheduled recordings and stop the running one when it hits
+/// its limits
+pub fn spawn(
+    scheduler: Arc<Mutex<Scheduler>>,
+    session: Arc<Mutex<SessionManager>>,
+    app: AppHandle,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+
+            let mut manager = session.lock().await;
+            if let Some(reason) = manager.limit_reached().await {
+                tracing::info!("Recording limit reached: {}", reason);
+                let _ = app.emit("recording-limit", serde_json::json!({ "reason": reason }));
+                if let Err(e) = manager.stop_recording(Some(app.clone())).await {
+                    tracing::error!("Failed to stop recording at its limit: {}", e);
+                }
+            }
+
+            let (due, mut missed) = {
+                let mut scheduler = scheduler.lock().await;
+                let due = scheduler.take_due(Local::now());
+                (due, scheduler.take_missed())
+            };
+            for schedule in due {
+                if manager.is_recording().await {
+                    tracing::warn!(
+                        "Skipping schedule {}, a recording is in progress",
+                        schedule.id
+                    );
+                    missed.push(schedule.missed("recording_in_progress"));
+                    continue;
+                }
+                tracing::info!("Starting scheduled recording {}", schedule.id);
+                let result = manager
+                    .start_recording(schedule.options.clone(), app.clone())
+                    .await;
+                let _ = app.emit(
+                    "schedule-triggered",
+                    serde_json::json!({
+                        "id": schedule.id,
+                        "started": result.is_ok(),
+                        "error": result.as_ref().err().map(|e| e.to_string()),
+                    }),
+                );
+            }
+            for schedule in missed {
+                let _ = app.emit("schedule-missed", schedule);
+            }
+        }
+    });
+}
+
+pub fn parse_time(time: &str) -> Result<DateTime<Local>> {
+    Ok(DateTime::parse_from_rfc3339(time)
+        .with_context(|| format!("Invalid timestamp {:?}, expected RFC 3339", time))?
+        .with_timezone(&Local))
+}
+
+/// Parsed cron expression, one set of allowed values per field
+struct CronExpression {
+    minutes: Vec<u32>,
+    hours: Vec<u32>,
+    days: Vec<u32>,
+    months: Vec<u32>,
+    weekdays: Vec<u32>,
+    /// Both day fields are restricted (neither starts with `*`), a day then only has to
+    /// match one of them
+    either_day: bool,
+}
+
+impl CronExpression {
+    fn parse(expression: &str) -> Result<Self> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(anyhow::anyhow!(
+                "Cron expression {:?} needs 5 fields: minute hour day month weekday",
+                expression
+            ));
+        };
+        Ok(Self {
+            minutes: parse_field(minutes, 0, 59)?,
+            hours: parse_field(hours, 0, 23)?,
+            days: parse_field(days, 1, 31)?,
+            months: parse_field(months, 1, 12)?,
+            // 7 is accepted as another spelling of Sunday
+            weekdays: parse_field(weekdays, 0, 7)?
+                .into_iter()
+                .map(|d| d % 7)
+                .collect(),
+            either_day: !days.starts_with('*') && !weekdays.starts_with('*'),
+        })
+    }
+
+    fn day_matches(&self, time: DateTime<Local>) -> bool {
+        let day = self.days.contains(&time.day());
+        let weekday = self
+            .weekdays
+            .contains(&time.weekday().num_days_from_sunday());
+        if self.either_day {
+            day || weekday
+        } else {
+            day && weekday
+        }
+    }
+
+    /// Scans minute by minute, giving up after a year without a match (e.g. 31 February)
+    fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        let mut time = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let limit = after + Duration::days(366);
+        while time <= limit {
+            if self.months.contains(&time.month())
+                && self.day_matches(time)
+                && self.hours.contains(&time.hour())
+                && self.minutes.contains(&time.minute())
+            {
+                return Some(time);
+            }
+            time += Duration::minutes(1);
+        }
+        None
+    }
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<Vec<u32>> {
+    let mut values = Vec::new();
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>()?),
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((a, b)) => (a.parse()?, b.parse()?),
+                None => {
+                    let value = range.parse()?;
+                    (value, if step > 1 { max } else { value })
+                }
+            },
+        };
+        if start < min || end > max || start > end || step == 0 {
+            return Err(anyhow::anyhow!("Invalid cron field {:?}", field));
+        }
+        values.extend((start..=end).step_by(step as usize));
+    }
+    Ok(values)
+}
@@ -1,16 +1,30 @@
-use crate::analytics::AnalyticsPipeline;
+use crate::analytics::idle::IdleAction;
+use crate::analytics::query::TimelineQuery;
+use crate::analytics::{AnalyticsConfig, AnalyticsPipeline, Marker};
 use crate::capture::{CaptureSource, CaptureTrait};
-use crate::encoder::Encoder;
+use crate::chapters;
+use crate::disk::{DiskGuard, DiskLevel};
+use crate::encoder::{BitratePreset, Encoder, SegmentConfig};
+use crate::encryption::{KeySource, RecordingKey};
+use crate::hooks::{self, HookContext};
+use crate::integrity;
 use crate::observability;
+use crate::recovery::Journal;
+use crate::replay::{self, ReplayBuffer, ReplayConfig, ReplaySnapshot};
+use crate::scheduler;
 use crate::system_metrics::SystemMetrics;
 use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tauri::{AppHandle, Emitter};
 use tokio::sync::Mutex;
 use tokio::time::{Duration, Instant};
 
+/// How often the recording journal is checkpointed while capturing
+const JOURNAL_INTERVAL: Duration = Duration::from_secs(5);
+
 
 #[derive(Clone, Copy, PartialEq)]
 pub enum RecordingState {
@@ -19,6 +33,41 @@ pub enum RecordingState {
     Paused,
 }
 
+/// Everything `start_recording` accepts, also stored with scheduled recordings
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecordingOptions {
+    pub monitor_id: Option<String>,
+    pub window_id: Option<String>,
+    pub analytics_config: Option<AnalyticsConfig>,
+    pub bitrate_preset: Option<BitratePreset>,
+    pub segment_config: Option<SegmentConfig>,
+    pub limits: RecordingLimits,
+    /// Encrypt the video, sidecars and thumbnails as they are written
+    pub encryption: Option<KeySource>,
+}
+
+/// The recording stops by itself at whichever limit is hit first
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecordingLimits {
+    /// Session seconds, pauses excluded
+    pub max_duration_seconds: Option<f64>,
+    /// Estimated total size of all segments, see `Segment::estimated_bytes`
+    pub max_size_mb: Option<u64>,
+    /// RFC 3339 wall-clock time
+    pub stop_at: Option<String>,
+}
+
+impl RecordingLimits {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(stop_at) = &self.stop_at {
+            scheduler::parse_time(stop_at)?;
+        }
+        Ok(())
+    }
+}
+
 pub struct SessionManager {
     state: Arc<Mutex<RecordingState>>,
     capture_source: Option<Arc<Mutex<crate::capture::Capture>>>,
@@ -27,7 +76,16 @@ pub struct SessionManager {
     system_metrics: Arc<SystemMetrics>,
     start_time: Option<Instant>,
     paused_duration: Arc<Mutex<Duration>>,
+    /// Start of a pause or idle auto-pause still in progress, not yet in `paused_duration`
+    pause_started: Arc<Mutex<Option<Instant>>>,
     output_path: Option<PathBuf>,
+    limits: RecordingLimits,
+    journal: Option<Arc<Mutex<Journal>>>,
+    disk: Option<Arc<Mutex<DiskGuard>>>,
+    /// Key of the running recording, if it is encrypted
+    encryption: Option<Arc<RecordingKey>>,
+    /// Last seconds of captured frames, kept while replay is enabled
+    replay: Arc<Mutex<Option<ReplayBuffer>>>,
     capture_task: Option<tokio::task::JoinHandle<()>>,
 }
 
@@ -41,7 +99,13 @@ impl SessionManager {
             system_metrics: Arc::new(SystemMetrics::new()),
             start_time: None,
             paused_duration: Arc::new(Mutex::new(Duration::ZERO)),
+            pause_started: Arc::new(Mutex::new(None)),
             output_path: None,
+            limits: RecordingLimits::default(),
+            journal: None,
+            disk: None,
+            encryption: None,
+            replay: Arc::new(Mutex::new(None)),
             capture_task: None,
         }
     }
@@ -72,18 +136,33 @@ impl SessionManager {
         }
     }
 
-    pub async fn start_recording(
-        &mut self,
-        monitor_id: Option<String>,
-        window_id: Option<String>,
-        app: AppHandle,
-    ) -> Result<()> {
+    pub async fn start_recording(&mut self, options: RecordingOptions, app: AppHandle) -> Result<()> {
         let current_state = *self.state.lock().await;
         if current_state == RecordingState::Recording {
             return Err(anyhow::anyhow!("Recording already in progress").into());
         }
 
+        let RecordingOptions {
+            monitor_id,
+            window_id,
+            analytics_config,
+            bitrate_preset,
+            segment_config,
+            limits,
+            encryption,
+        } = options;
+        limits.validate()?;
+
+        // Argon2 takes a moment, and a bad keyfile should fail before anything starts
+        let encryption = match encryption {
+            Some(source) => Some(Arc::new(
+                tokio::task::spawn_blocking(move || RecordingKey::new(&source)).await??,
+            )),
+            None => None,
+        };
+
         observability::record_event("recording_started", &[]);
+        let (journal_monitor_id, journal_window_id) = (monitor_id.clone(), window_id.clone());
 
         // Determine capture source
         let source = if let Some(mon_id) = monitor_id {
@@ -94,6 +173,8 @@ impl SessionManager {
             return Err(anyhow::anyhow!("No capture source specified").into());
         };
 
+        let source_label = source.label();
+
         // Initialize capture
         let mut capture = crate::capture::create_capture(source)
             .await
@@ -107,10 +188,22 @@ impl SessionManager {
         // Generate output path
         let output_path = self.generate_output_path()?;
 
+        // Check for space before the encoder creates the output file
+        let bitrate_preset = bitrate_preset.unwrap_or_default();
+        let output_dir = output_path.parent().unwrap_or(&output_path);
+        let mut disk = DiskGuard::new(output_dir, bitrate_preset.bytes_per_second());
+        disk.check_before_start()?;
+
         // Initialize encoder
-        let mut encoder = Encoder::new(output_path.clone())
-            .await
-            .context("Failed to create encoder")?;
+        let segment_config = segment_config.unwrap_or_default();
+        let mut encoder = Encoder::new(
+            output_path.clone(),
+            bitrate_preset,
+            segment_config.clone(),
+            encryption.clone(),
+        )
+        .await
+        .context("Failed to create encoder")?;
 
         encoder
             .initialize()
@@ -118,13 +211,39 @@ impl SessionManager {
             .context("Failed to initialize encoder")?;
 
         // Initialize analytics
-        let analytics = AnalyticsPipeline::new();
+        let analytics_config = analytics_config.unwrap_or_default();
+        let idle_action = analytics_config.idle_action;
+        let journal = Journal::new(
+            output_path.clone(),
+            journal_monitor_id,
+            journal_window_id,
+            bitrate_preset,
+            segment_config,
+            analytics_config.clone(),
+            encryption.is_some(),
+        );
+        journal.write().context("Failed to write recording journal")?;
+        let mut analytics = AnalyticsPipeline::new(
+            analytics_config,
+            bitrate_preset.kbps(),
+            &output_path,
+            encryption.clone(),
+        )
+        .context("Failed to create analytics pipeline")?;
+        analytics.set_source(source_label);
 
         // Wrap in Arc<Mutex> for shared access
         let capture_arc = Arc::new(Mutex::new(capture));
         let encoder_arc = Arc::new(Mutex::new(encoder));
         let analytics_arc = Arc::new(Mutex::new(analytics));
         let metrics_arc = self.system_metrics.clone();
+        let journal_arc = Arc::new(Mutex::new(journal));
+        let disk_arc = Arc::new(Mutex::new(disk));
+        let replay_arc = self.replay.clone();
+        // Session time starts over, frames of an earlier recording would sort before it
+        if let Some(buffer) = replay_arc.lock().await.as_mut() {
+            buffer.reset();
+        }
 
         self.capture_source = Some(capture_arc.clone());
         self.encoder = Some(encoder_arc.clone());
@@ -132,12 +251,18 @@ impl SessionManager {
         *self.state.lock().await = RecordingState::Recording;
         self.start_time = Some(Instant::now());
         *self.paused_duration.lock().await = Duration::ZERO;
+        *self.pause_started.lock().await = None;
         self.output_path = Some(output_path.clone());
+        self.limits = limits;
+        self.journal = Some(journal_arc.clone());
+        self.disk = Some(disk_arc.clone());
+        self.encryption = encryption;
 
         // Start capture loop in background task
         let app_clone = app.clone();
         let start_time_clone = self.start_time.unwrap();
         let paused_duration_arc = self.paused_duration.clone();
+        let pause_started_arc = self.pause_started.clone();
         let state_arc_clone = self.state.clone();
         
         let task = tokio::spawn(async move {
@@ -150,6 +275,11 @@ impl SessionManager {
                 app_clone,
                 start_time_clone,
                 paused_duration_arc,
+                pause_started_arc,
+                idle_action,
+                journal_arc,
+                replay_arc,
+                disk_arc,
             )
             .await;
         });
@@ -172,7 +302,8 @@ impl SessionManager {
         Ok(())
     }
 
-    pub async fn stop_recording(&mut self, app: Option<AppHandle>) -> Result<String> {
+    /// Stop and finalize the recording, returning the segment manifest
+    pub async fn stop_recording(&mut self, app: Option<AppHandle>) -> Result<Value> {
         let current_state = *self.state.lock().await;
         if current_state == RecordingState::Stopped {
             return Err(anyhow::anyhow!("No recording in progress").into());
@@ -195,25 +326,57 @@ impl SessionManager {
         }
 
         // Finalize encoder
+        let mut manifest = Value::Null;
         if let Some(encoder) = self.encoder.take() {
             let mut encoder_guard = encoder.lock().await;
             encoder_guard.finalize().await?;
+            manifest = encoder_guard.manifest();
         }
 
         // Save analytics
         if let Some(analytics) = self.analytics.take() {
-            let analytics_guard = analytics.lock().await;
+            let mut analytics_guard = analytics.lock().await;
             if let Some(ref path) = self.output_path {
                 analytics_guard.save_metadata(path).await?;
+                let chapters = analytics_guard.chapters();
+                let key = self.encryption.as_deref();
+                if let Err(e) = chapters::write_tracks(path, &chapters, key).await {
+                    tracing::warn!("Failed to write chapters: {}", e);
+                }
             }
         }
 
-        let output = self
-            .output_path
-            .as_ref()
-            .and_then(|p| p.to_str())
-            .unwrap_or("")
-            .to_string();
+        // Everything is finalized, nothing left to recover after a crash
+        if let Some(journal) = self.journal.take() {
+            journal.lock().await.remove();
+        }
+
+        // Sealing reads the whole recording and hooks can run for minutes, neither may hold
+        // up the session
+        if let Some(path) = self.output_path.clone() {
+            let context = HookContext::from_manifest(&path, &manifest);
+            let app = app.clone();
+            tokio::spawn(async move {
+                if let Err(e) = integrity::seal(&path).await {
+                    tracing::error!("Failed to seal {:?}: {}", path, e);
+                }
+                // Transcode, copy, notify... whatever the user configured
+                if let Some(app) = app {
+                    hooks::run_configured(context, app).await;
+                }
+            });
+        }
+
+        // Segmented recordings are only complete together, so point at their manifest
+        let output = if manifest["segmented"].as_bool().unwrap_or(false) {
+            manifest["manifest_path"].as_str().unwrap_or("").to_string()
+        } else {
+            self.output_path
+                .as_ref()
+                .and_then(|p| p.to_str())
+                .unwrap_or("")
+                .to_string()
+        };
 
         // Emit stopped event with output path
         if let Some(app) = app {
@@ -223,15 +386,20 @@ impl SessionManager {
                     "is_recording": false,
                     "is_paused": false,
                     "duration": 0.0,
-                    "output_path": output.clone(),
+                    "output_path": output,
+                    "manifest": manifest,
                 }),
             );
         }
 
         self.start_time = None;
+        self.limits = RecordingLimits::default();
+        self.disk = None;
+        self.encryption = None;
         *self.paused_duration.lock().await = Duration::ZERO;
+        *self.pause_started.lock().await = None;
 
-        Ok(output)
+        Ok(manifest)
     }
 
     pub async fn pause_recording(&mut self) -> Result<()> {
@@ -251,6 +419,51 @@ impl SessionManager {
         }
     }
 
+    pub async fn is_recording(&self) -> bool {
+        *self.state.lock().await != RecordingState::Stopped
+    }
+
+    /// Which limit of the running recording has been hit, if any
+    pub async fn limit_reached(&self) -> Option<&'static str> {
+        if !self.is_recording().await {
+            return None;
+        }
+
+        if let (Some(max), Some(start)) = (self.limits.max_duration_seconds, self.start_time) {
+            let ongoing_pause = self
+                .pause_started
+                .lock()
+                .await
+                .map_or(Duration::ZERO, |t| t.elapsed());
+            let duration = start.elapsed().as_secs_f64()
+                - (*self.paused_duration.lock().await + ongoing_pause).as_secs_f64();
+            if duration >= max {
+                return Some("max_duration");
+            }
+        }
+
+        if let (Some(max), Some(encoder)) = (self.limits.max_size_mb, &self.encoder) {
+            if encoder.lock().await.estimated_bytes_written() >= max * 1024 * 1024 {
+                return Some("max_size");
+            }
+        }
+
+        let stop_at = self.limits.stop_at.as_deref();
+        if stop_at
+            .and_then(|t| scheduler::parse_time(t).ok())
+            .is_some_and(|t| chrono::Local::now() >= t)
+        {
+            return Some("stop_at");
+        }
+
+        if let Some(disk) = &self.disk {
+            if disk.lock().await.level() == DiskLevel::Critical {
+                return Some("disk_full");
+            }
+        }
+        None
+    }
+
     pub async fn get_recording_status(&self) -> Result<serde_json::Value, String> {
         let state = *self.state.lock().await;
         let duration = if let Some(start) = self.start_time {
@@ -267,12 +480,100 @@ impl SessionManager {
         }))
     }
 
-    pub async fn get_timeline_data(&self) -> Result<Vec<Value>, String> {
+    /// Bookmark the current point of the recording, `kind` defaults to "bookmark"
+    pub async fn add_marker(
+        &self,
+        label: String,
+        kind: Option<String>,
+        app: Option<&AppHandle>,
+    ) -> Result<Marker> {
+        let state = *self.state.lock().await;
+        let analytics = match (&self.analytics, state) {
+            (Some(analytics), RecordingState::Recording | RecordingState::Paused) => analytics,
+            _ => return Err(anyhow::anyhow!("No recording in progress")),
+        };
+
+        let marker = analytics
+            .lock()
+            .await
+            .add_marker(label, kind.unwrap_or_else(|| "bookmark".to_string()));
+        observability::record_event("marker_added", &[]);
+
+        if let Some(app) = app {
+            let _ = app.emit(
+                "recording-update",
+                serde_json::json!({
+                    "is_recording": state == RecordingState::Recording,
+                    "is_paused": state == RecordingState::Paused,
+                    "duration": marker.time,
+                    "marker": marker,
+                }),
+            );
+        }
+        Ok(marker)
+    }
+
+    /// Turn the replay buffer on with `config`, or off with `None`. While on, the last
+    /// seconds of every recording are kept for `save_replay`.
+    pub async fn set_replay_buffer(&self, config: Option<ReplayConfig>) {
+        *self.replay.lock().await = config.map(ReplayBuffer::new);
+        observability::record_event("replay_buffer_configured", &[]);
+    }
+
+    /// Take the replay window and its timeline for `replay::save`, the buffer keeps
+    /// filling meanwhile
+    pub async fn replay_snapshot(&self) -> Result<ReplaySnapshot> {
+        let frames = match self.replay.lock().await.as_ref() {
+            Some(buffer) => buffer.snapshot(),
+            None => return Err(anyhow::anyhow!("Replay buffer is not enabled")),
+        };
+        let (Some(first), Some(last)) = (frames.first(), frames.last()) else {
+            return Err(anyhow::anyhow!("Replay buffer is empty"));
+        };
+        let (start, end) = (first.time(), last.time());
+
+        let timeline = match &self.analytics {
+            Some(analytics) => analytics.lock().await.timeline_slice(start, end).await,
+            None => Vec::new(),
+        };
+
+        let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
+        let mut output_path = recordings_dir()?;
+        output_path.push(format!("replay_{}.mkv", timestamp));
+
+        Ok(ReplaySnapshot {
+            frames,
+            timeline,
+            output_path,
+            source_path: self.output_path.clone(),
+            key: self.encryption.clone(),
+        })
+    }
+
+    pub async fn get_timeline_data(&self, query: TimelineQuery) -> Result<Value, String> {
+        if let Some(analytics) = &self.analytics {
+            // The lock is only held to take the reader, capture needs it for every frame
+            let reader = analytics.lock().await.timeline_reader();
+            Ok(reader.get_timeline_data(&query).await)
+        } else {
+            Ok(serde_json::json!({
+                "entries": [],
+                "cursor": 0,
+                "truncated": false,
+            }))
+        }
+    }
+
+    pub async fn get_motion_heatmap(
+        &self,
+        from: Option<f64>,
+        to: Option<f64>,
+    ) -> Result<Value, String> {
         if let Some(analytics) = &self.analytics {
             let analytics_guard = analytics.lock().await;
-            Ok(analytics_guard.get_timeline_data().await)
+            Ok(analytics_guard.get_motion_heatmap(from, to))
         } else {
-            Ok(vec![])
+            Err("No recording in progress".to_string())
         }
     }
 
@@ -285,11 +586,19 @@ impl SessionManager {
         app: AppHandle,
         start_time: Instant,
         paused_duration: Arc<Mutex<Duration>>,
+        pause_started: Arc<Mutex<Option<Instant>>>,
+        idle_action: IdleAction,
+        journal: Arc<Mutex<Journal>>,
+        replay: Arc<Mutex<Option<ReplayBuffer>>>,
+        disk: Arc<Mutex<DiskGuard>>,
     ) {
         let mut frame_count = 0u64;
         let mut last_metrics_update = Instant::now();
+        let mut last_checkpoint = Instant::now();
         let mut last_state_update = Instant::now();
         let mut paused_start: Option<Instant> = None;
+        // Set while the analytics report an idle screen
+        let mut idle_start: Option<Instant> = None;
 
         loop {
             // Check if recording is paused or stopped
@@ -301,6 +610,12 @@ impl SessionManager {
             if current_state == RecordingState::Paused {
                 if paused_start.is_none() {
                     paused_start = Some(Instant::now());
+                    // The manual pause takes over from an idle auto-pause, so the time
+                    // after this isn't excluded twice. Idle is detected again on resume.
+                    let auto_paused = Self::auto_paused(idle_start.take(), idle_action);
+                    *paused_duration.lock().await += auto_paused;
+                    *pause_started.lock().await =
+                        Self::pause_started(paused_start, idle_start, idle_action);
                 }
                 tokio::time::sleep(Duration::from_millis(100)).await;
                 continue;
@@ -310,6 +625,7 @@ impl SessionManager {
             if let Some(pause_start_time) = paused_start.take() {
                 let mut paused_dur = paused_duration.lock().await;
                 *paused_dur += pause_start_time.elapsed();
+                *pause_started.lock().await = Self::pause_started(None, idle_start, idle_action);
             }
 
             // Capture frame
@@ -322,26 +638,52 @@ impl SessionManager {
                 Ok(Some(frame)) => {
                     frame_count += 1;
 
-                    // Process analytics
-                    {
+                    // Session time excluding pauses, so analytics line up with the encoded video
+                    let session_time = {
+                        let paused_dur = *paused_duration.lock().await
+                            + Self::auto_paused(idle_start, idle_action);
+                        (start_time.elapsed().as_secs_f64() - paused_dur.as_secs_f64()).max(0.0)
+                    };
+
+                    // Process analytics, there is no audio capture to feed silence detection
+                    let summary = {
                         let mut analytics_guard = analytics.lock().await;
-                        analytics_guard.process_frame(&frame).await;
+                        analytics_guard.process_frame(&frame, session_time, None).await
+                    };
+
+                    if summary.idle && idle_start.is_none() {
+                        idle_start = Some(Instant::now());
+                        *pause_started.lock().await =
+                            Self::pause_started(None, idle_start, idle_action);
+                        observability::record_event("recording_idle", &[]);
+                    } else if !summary.idle && idle_start.is_some() {
+                        let auto_paused = Self::auto_paused(idle_start.take(), idle_action);
+                        *paused_duration.lock().await += auto_paused;
+                        *pause_started.lock().await = None;
+                        observability::record_event("recording_active", &[]);
+                    }
+
+                    for warning in &summary.warnings {
+                        let _ = app.emit("analytics-warning", warning);
                     }
 
-                    // Encode frame
-                    {
+                    // Encode frame, unless it is an idle frame the session is told to drop
+                    if idle_start.is_none() || idle_action == IdleAction::Ignore {
+                        replay::feed(&replay, &frame, session_time).await;
                         let mut encoder_guard = encoder.lock().await;
-                        if let Err(e) = encoder_guard.encode_frame(&frame).await {
+                        if let Err(e) = encoder_guard.encode_frame(&frame, session_time).await {
                             tracing::error!("Encoding error: {}", e);
                             break;
                         }
+                    } else if idle_action == IdleAction::SkipFrames {
+                        encoder.lock().await.hold_frame(session_time);
                     }
 
                     // Update metrics periodically
                     if last_metrics_update.elapsed() > Duration::from_secs(1) {
-                        let mut metrics = {
+                        let (mut metrics, estimated_bytes) = {
                             let encoder_guard = encoder.lock().await;
-                            encoder_guard.get_metrics()
+                            (encoder_guard.get_metrics(), encoder_guard.estimated_bytes_written())
                         };
 
                         // Add system metrics
@@ -349,9 +691,35 @@ impl SessionManager {
                         metrics["cpu_usage"] = sys_metrics["cpu_usage"].clone();
                         metrics["memory_usage"] = sys_metrics["memory_usage"].clone();
 
+                        // Disk usage, the scheduler ticker stops the recording once it is critical
+                        let mut disk_guard = disk.lock().await;
+                        if let Some(warning) = disk_guard.check(estimated_bytes) {
+                            let _ = app.emit("disk-space-warning", warning);
+                        }
+                        metrics["estimated_write_throughput"] =
+                            serde_json::json!(disk_guard.estimated_throughput());
+                        metrics["disk_free"] = serde_json::json!(disk_guard.free_bytes());
+                        drop(disk_guard);
+
                         let _ = app.emit("metrics-update", metrics);
                         last_metrics_update = Instant::now();
                     }
+
+                    // Checkpoint the journal, a crash loses at most the last interval
+                    if last_checkpoint.elapsed() > JOURNAL_INTERVAL {
+                        let manifest = encoder.lock().await.manifest();
+                        let checkpoint = analytics.lock().await.checkpoint();
+                        let result = match checkpoint {
+                            Ok(entries) => {
+                                journal.lock().await.checkpoint(manifest, entries, session_time)
+                            }
+                            Err(e) => Err(e),
+                        };
+                        if let Err(e) = result {
+                            tracing::warn!("Failed to checkpoint recording journal: {}", e);
+                        }
+                        last_checkpoint = Instant::now();
+                    }
                 }
                 Ok(None) => {
                     // No frame available, continue
@@ -366,7 +734,8 @@ impl SessionManager {
 
             // Emit state update periodically
             if last_state_update.elapsed() > Duration::from_millis(500) {
-                let paused_dur = *paused_duration.lock().await;
+                let paused_dur =
+                    *paused_duration.lock().await + Self::auto_paused(idle_start, idle_action);
                 let duration = if paused_start.is_some() {
                     // Currently paused
                     start_time.elapsed().as_secs_f64() - paused_dur.as_secs_f64()
@@ -379,6 +748,9 @@ impl SessionManager {
                     serde_json::json!({
                         "is_recording": current_state == RecordingState::Recording,
                         "is_paused": current_state == RecordingState::Paused,
+                        "is_idle": idle_start.is_some(),
+                        "idle_action": idle_action,
+                        "idle_for": idle_start.map(|t| t.elapsed().as_secs_f64()).unwrap_or(0.0),
                         "duration": duration,
                     }),
                 );
@@ -389,16 +761,57 @@ impl SessionManager {
         tracing::info!("Capture loop finished after {} frames", frame_count);
     }
 
+    /// Start of the time currently kept off the session clock by a pause, an idle
+    /// auto-pause or both
+    fn pause_started(
+        paused_start: Option<Instant>,
+        idle_start: Option<Instant>,
+        idle_action: IdleAction,
+    ) -> Option<Instant> {
+        let idle_start = idle_start.filter(|_| idle_action == IdleAction::Pause);
+        match (paused_start, idle_start) {
+            (Some(paused), Some(idle)) => Some(paused.min(idle)),
+            (paused, idle) => paused.or(idle),
+        }
+    }
+
+    /// Time excluded from the session clock by an ongoing idle auto-pause
+    fn auto_paused(idle_start: Option<Instant>, idle_action: IdleAction) -> Duration {
+        match idle_start {
+            Some(start) if idle_action == IdleAction::Pause => start.elapsed(),
+            _ => Duration::ZERO,
+        }
+    }
+
     fn generate_output_path(&self) -> Result<PathBuf> {
         let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
         let filename = format!("recording_{}.mkv", timestamp);
-        let mut path = dirs::video_dir()
-            .or_else(|| dirs::home_dir())
-            .ok_or_else(|| anyhow::anyhow!("Could not determine output directory"))?;
-        path.push("ScreenRecordings");
-        std::fs::create_dir_all(&path)?;
+        let mut path = recordings_dir()?;
         path.push(filename);
         Ok(path)
     }
+
+    /// Path of the recording in progress, if any
+    pub fn active_output_path(&self) -> Option<&Path> {
+        self.output_path.as_deref().filter(|_| self.journal.is_some())
+    }
 }
 
+/// Directory new recordings are written to, created on first use
+pub fn recordings_dir() -> Result<PathBuf> {
+    let mut path = dirs::video_dir()
+        .or_else(dirs::home_dir)
+        .ok_or_else(|| anyhow::anyhow!("Could not determine output directory"))?;
+    path.push("ScreenRecordings");
+    std::fs::create_dir_all(&path)?;
+    Ok(path)
+}
+
+/// App settings directory (schedules, retention policy), created on first use
+pub fn config_dir() -> Result<PathBuf> {
+    let mut path =
+        dirs::config_dir().ok_or_else(|| anyhow::anyhow!("Could not determine config directory"))?;
+    path.push("com.screenrecorder.app");
+    std::fs::create_dir_all(&path)?;
+    Ok(path)
+}
@@ -5,6 +5,7 @@ import DevicePicker from "./components/DevicePicker";
 import RecordingControls from "./components/RecordingControls";
 import Timeline from "./components/Timeline";
 import MetricsPanel from "./components/MetricsPanel";
+import Notices, { Notice } from "./components/Notices";
 import "./App.css";
 
 // Check if running in Tauri
@@ -13,6 +14,8 @@ const isTauri = typeof window !== "undefined" && "__TAURI_INTERNALS__" in window
 interface RecordingState {
   isRecording: boolean;
   isPaused: boolean;
+  // Screen unchanged for a while, see `idle_action` in the analytics config
+  isIdle: boolean;
   duration: number;
 }
 
@@ -23,6 +26,7 @@ interface Metrics {
   encodeLatency: number;
   cpuUsage: number;
   memoryUsage: number;
+  estimatedWriteThroughput: number;
 }
 
 function App() {
@@ -31,6 +35,7 @@ function App() {
   const [recordingState, setRecordingState] = useState<RecordingState>({
     isRecording: false,
     isPaused: false,
+    isIdle: false,
     duration: 0,
   });
   const [metrics, setMetrics] = useState<Metrics>({
@@ -40,9 +45,17 @@ function App() {
     encodeLatency: 0,
     cpuUsage: 0,
     memoryUsage: 0,
+    estimatedWriteThroughput: 0,
   });
   const [selectedMonitor, setSelectedMonitor] = useState<string | null>(null);
   const [selectedWindow, setSelectedWindow] = useState<string | null>(null);
+  const [notices, setNotices] = useState<Notice[]>([]);
+
+  // A notice with the same id replaces the previous one
+  const showNotice = (notice: Notice) =>
+    setNotices((prev) => [...prev.filter((n) => n.id !== notice.id), notice]);
+  const dismissNotice = (id: string) =>
+    setNotices((prev) => prev.filter((n) => n.id !== id));
   
   console.log("App.tsx: State initialized");
 
@@ -57,6 +70,7 @@ function App() {
         setRecordingState({
           isRecording: status.is_recording || false,
           isPaused: status.is_paused || false,
+          isIdle: false,
           duration: status.duration || 0,
         });
       } catch (error) {
@@ -65,7 +79,54 @@ function App() {
     };
     
     syncStatus();
-    
+
+    // Offer to recover recordings left unfinished by a crash
+    const resolveUnfinished = async (recording: any, recover: boolean) => {
+      const id = `recovery-${recording.journal_path}`;
+      dismissNotice(id);
+      try {
+        if (recover) {
+          const manifest = await invoke<any>("recover_recording", { journalPath: recording.journal_path });
+          showNotice({
+            id,
+            level: "info",
+            message: `Recovered ${manifest.segments?.length || 0} segment(s) of:\n${recording.video_path}`,
+          });
+        } else {
+          await invoke("discard_unfinished_recording", { journalPath: recording.journal_path });
+        }
+      } catch (error) {
+        console.error("Failed to resolve unfinished recording:", error);
+        showNotice({
+          id,
+          level: "critical",
+          message: `Could not ${recover ? "recover" : "discard"} ${recording.video_path}: ${error}`,
+        });
+      }
+    };
+
+    const checkUnfinished = async () => {
+      try {
+        const unfinished = await invoke<any[]>("list_unfinished_recordings");
+        for (const recording of unfinished) {
+          const minutes = Math.round((recording.duration || 0) / 60);
+          showNotice({
+            id: `recovery-${recording.journal_path}`,
+            level: "warning",
+            message: `A recording started ${recording.started_at} (about ${minutes} min) was not finished.`,
+            actions: [
+              { label: "Recover", onClick: () => resolveUnfinished(recording, true) },
+              { label: "Discard", onClick: () => resolveUnfinished(recording, false) },
+            ],
+          });
+        }
+      } catch (error) {
+        console.error("Failed to check for unfinished recordings:", error);
+      }
+    };
+
+    checkUnfinished();
+
     // Listen for recording events
     const unlistenPromise = listen("recording-update", (event) => {
       const data = event.payload as any;
@@ -73,6 +134,7 @@ function App() {
       setRecordingState({
         isRecording: data.is_recording || false,
         isPaused: data.is_paused || false,
+        isIdle: data.is_idle || false,
         duration: data.duration || 0,
       });
       
@@ -104,14 +166,63 @@ function App() {
         encodeLatency: data.encode_latency || 0,
         cpuUsage: data.cpu_usage || 0,
         memoryUsage: data.memory_usage || 0,
+        estimatedWriteThroughput: data.estimated_write_throughput || 0,
       });
     }).catch((error) => {
       console.warn("Failed to listen to metrics-update events:", error);
     });
 
+    // Warn when the recording volume runs low, a critical level stops the recording
+    const unlistenDiskPromise = listen("disk-space-warning", (event) => {
+      const data = event.payload as any;
+      const freeMb = Math.round((data.free_bytes || 0) / 1024 / 1024);
+      showNotice({
+        id: "disk-space",
+        level: data.level === "critical" ? "critical" : "warning",
+        message:
+          data.level === "critical"
+            ? `Disk almost full (${freeMb} MB free). The recording is being stopped and saved.`
+            : `Disk space is running low: ${freeMb} MB free, about ${Math.round((data.estimated_seconds_left || 0) / 60)} min of recording left (estimated).`,
+      });
+    }).catch((error) => {
+      console.warn("Failed to listen to disk-space-warning events:", error);
+    });
+
+    // The marker hotkey is taken by another app, markers can still be added from the UI
+    const unlistenShortcutPromise = listen("shortcut-unavailable", (event) => {
+      const data = event.payload as any;
+      showNotice({
+        id: "shortcut-unavailable",
+        level: "warning",
+        message: `The marker hotkey ${data.shortcut} could not be registered: ${data.error}`,
+      });
+    }).catch((error) => {
+      console.warn("Failed to listen to shortcut-unavailable events:", error);
+    });
+
+    // A scheduled recording did not start, e.g. the app was closed at the time
+    const unlistenScheduleMissedPromise = listen("schedule-missed", (event) => {
+      const data = event.payload as any;
+      const reasons: Record<string, string> = {
+        app_closed: "the app was closed",
+        late: "the app could not start it in time",
+        recording_in_progress: "another recording was in progress",
+      };
+      showNotice({
+        id: `schedule-missed-${data.id}-${data.run}`,
+        level: "warning",
+        message: `A scheduled recording due ${data.run} did not start: ${reasons[data.reason] || data.reason}.`,
+      });
+    }).catch((error) => {
+      console.warn("Failed to listen to schedule-missed events:", error);
+    });
+
     return () => {
       unlistenPromise.then((fn) => fn && fn()).catch(() => {});
       unlistenMetricsPromise.then((fn) => fn && fn()).catch(() => {});
+      unlistenDiskPromise.then((fn) => fn && fn()).catch(() => {});
+      unlistenShortcutPromise.then((fn) => fn && fn()).catch(() => {});
+      unlistenScheduleMissedPromise.then((fn) => fn && fn()).catch(() => {});
     };
   }, [isTauri]);
 
@@ -136,6 +247,7 @@ function App() {
         ...prev,
         isRecording: true,
         isPaused: false,
+        isIdle: false,
         duration: 0,
       }));
     } catch (error) {
@@ -148,6 +260,7 @@ function App() {
           setRecordingState({
             isRecording: status.is_recording || false,
             isPaused: status.is_paused || false,
+            isIdle: false,
             duration: status.duration || 0,
           });
         } catch (syncError) {
@@ -209,6 +322,8 @@ function App() {
         )}
       </header>
 
+      <Notices notices={notices} onDismiss={dismissNotice} />
+
       <main className="app-main">
         <div className="left-panel">
           <DevicePicker
@@ -221,6 +336,7 @@ function App() {
           <RecordingControls
             isRecording={recordingState.isRecording}
             isPaused={recordingState.isPaused}
+            isIdle={recordingState.isIdle}
             duration={recordingState.duration}
             onStart={handleStartRecording}
             onStop={handleStopRecording}
@@ -7,6 +7,8 @@ interface Metrics {
   encodeLatency: number;
   cpuUsage: number;
   memoryUsage: number;
+  // From the bitrate preset, not measured on disk
+  estimatedWriteThroughput: number;
 }
 
 interface MetricsPanelProps {
@@ -44,6 +46,12 @@ function MetricsPanel({ metrics }: MetricsPanelProps) {
             {(metrics.memoryUsage / 1024 / 1024).toFixed(1)} MB
           </div>
         </div>
+        <div className="metric-item">
+          <div className="metric-label">Est. Disk Write</div>
+          <div className="metric-value">
+            {(metrics.estimatedWriteThroughput / 1024 / 1024).toFixed(1)} MB/s
+          </div>
+        </div>
       </div>
     </div>
   );
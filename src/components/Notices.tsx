@@ -0,0 +1,46 @@
+import "./Notices.css";
+
+export interface NoticeAction {
+  label: string;
+  onClick: () => void;
+}
+
+export interface Notice {
+  id: string;
+  level: "info" | "warning" | "critical";
+  message: string;
+  // Without actions the notice gets a dismiss button
+  actions?: NoticeAction[];
+}
+
+interface NoticesProps {
+  notices: Notice[];
+  onDismiss: (id: string) => void;
+}
+
+function Notices({ notices, onDismiss }: NoticesProps) {
+  if (notices.length === 0) return null;
+
+  return (
+    <div className="notices">
+      {notices.map((notice) => (
+        <div key={notice.id} className={`notice ${notice.level}`} role="alert">
+          <div className="notice-message">{notice.message}</div>
+          <div className="notice-actions">
+            {notice.actions?.length ? (
+              notice.actions.map((action) => (
+                <button key={action.label} onClick={action.onClick}>
+                  {action.label}
+                </button>
+              ))
+            ) : (
+              <button onClick={() => onDismiss(notice.id)}>Dismiss</button>
+            )}
+          </div>
+        </div>
+      ))}
+    </div>
+  );
+}
+
+export default Notices;
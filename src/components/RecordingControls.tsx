@@ -3,6 +3,7 @@ import "./RecordingControls.css";
 interface RecordingControlsProps {
   isRecording: boolean;
   isPaused: boolean;
+  isIdle: boolean;
   duration: number;
   onStart: () => void;
   onStop: () => void;
@@ -12,6 +13,7 @@ interface RecordingControlsProps {
 function RecordingControls({
   isRecording,
   isPaused,
+  isIdle,
   duration,
   onStart,
   onStop,
@@ -31,8 +33,10 @@ function RecordingControls({
       <div className="timer">
         <div className="timer-display">{formatTime(duration)}</div>
         {isRecording && (
-          <div className={`recording-indicator ${isPaused ? "paused" : ""}`}>
-            {isPaused ? "⏸ PAUSED" : "● REC"}
+          <div
+            className={`recording-indicator ${isPaused ? "paused" : isIdle ? "idle" : ""}`}
+          >
+            {isPaused ? "⏸ PAUSED" : isIdle ? "◌ IDLE" : "● REC"}
           </div>
         )}
         {!isRecording && duration === 0 && (
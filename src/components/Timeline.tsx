@@ -16,10 +16,20 @@ interface TimelineData {
   time: number;
   colorDominance: number;
   brightness: number;
-  audioLevel: number;
+  // Null when the recording has no audio
+  audioLevel: number | null;
   sceneChange: boolean;
 }
 
+interface TimelineResponse {
+  entries: TimelineData[];
+  cursor: number;
+  truncated: boolean;
+}
+
+// Keep chart payloads small, the backend buckets the whole recording down to this
+const MAX_POINTS = 500;
+
 function Timeline({ isRecording }: TimelineProps) {
   const [timelineData, setTimelineData] = useState<TimelineData[]>([]);
 
@@ -31,9 +41,9 @@ function Timeline({ isRecording }: TimelineProps) {
     
     const pollTimelineData = async () => {
       try {
-        const data = await invoke<TimelineData[]>("get_timeline_data");
-        if (data.length > 0) {
-          setTimelineData(data);
+        const data = await invoke<TimelineResponse>("get_timeline_data", { maxPoints: MAX_POINTS });
+        if (data.entries.length > 0) {
+          setTimelineData(data.entries);
         }
       } catch (error) {
         console.error("Failed to poll timeline data:", error);
@@ -60,8 +70,8 @@ function Timeline({ isRecording }: TimelineProps) {
   const loadTimelineData = async () => {
     if (!isTauri) return;
     try {
-      const data = await invoke<TimelineData[]>("get_timeline_data");
-      setTimelineData(data);
+      const data = await invoke<TimelineResponse>("get_timeline_data", { maxPoints: MAX_POINTS });
+      setTimelineData(data.entries);
     } catch (error) {
       console.error("Failed to load timeline data:", error);
     }